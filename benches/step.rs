@@ -0,0 +1,19 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use gameoflife::bench::{self, Backend};
+use gameoflife::game::GameOfLife;
+
+// One simulation step of every backend on the smallest grid of the matrix,
+// `gameoflife bench` covers the full matrix
+fn step(c: &mut Criterion) {
+    let mut group = c.benchmark_group("step");
+    for case in bench::matrix(&Backend::ALL, true) {
+        let mut game = GameOfLife::new(case.config());
+        group.bench_function(BenchmarkId::from_parameter(case.name()), |b| {
+            b.iter(|| game.step())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, step);
+criterion_main!(benches);
@@ -0,0 +1,130 @@
+use crate::cell::Cell;
+use crate::grid::Grid;
+
+// Side length of the tiles activity is tracked in
+pub const SIZE: usize = 16;
+
+// Tiles of the grid that need simulating in the next step. A tile is quiet when
+// nothing within the neighbourhood radius of it can influence its cells.
+#[derive(Debug, Clone, Default)]
+pub struct Activity {
+    w: usize,
+    h: usize,
+    active: Vec<bool>,
+}
+
+impl Activity {
+    // Marks every tile within `radius` of a tile that changed between `prev`
+    // and `next`, or of one with life in `next` when `by_change` is false
+    pub fn track(prev: &Grid<Cell>, next: &Grid<Cell>, radius: usize, by_change: bool) -> Self {
+        let w = next.width().div_ceil(SIZE);
+        let h = next.height().div_ceil(SIZE);
+        let mut busy = vec![false; w * h];
+        for (y, (old, new)) in prev.rows().zip(next.rows()).enumerate() {
+            for (x, (old, new)) in old.iter().zip(new).enumerate() {
+                let busy_cell = if by_change {
+                    old != new
+                } else {
+                    new.is_alive()
+                };
+                if busy_cell {
+                    busy[(y / SIZE) * w + x / SIZE] = true;
+                }
+            }
+        }
+
+        let reach = radius.div_ceil(SIZE);
+        let mut active = vec![false; w * h];
+        for ty in 0..h {
+            for tx in 0..w {
+                if !busy[ty * w + tx] {
+                    continue;
+                }
+                for ny in ty.saturating_sub(reach)..(ty + reach + 1).min(h) {
+                    for nx in tx.saturating_sub(reach)..(tx + reach + 1).min(w) {
+                        active[ny * w + nx] = true;
+                    }
+                }
+            }
+        }
+        Self { w, h, active }
+    }
+
+    pub fn is_active(&self, x: usize, y: usize) -> bool {
+        self.active[(y / SIZE) * self.w + x / SIZE]
+    }
+
+    // Active tiles out of all tiles
+    pub fn count(&self) -> (usize, usize) {
+        let active = self.active.iter().filter(|&&active| active).count();
+        (active, self.w * self.h)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cell::{CellState, Health};
+
+    fn alive() -> Cell {
+        Cell::prey(CellState::Alive {
+            age: 1,
+            energy: 0,
+            health: Health::Susceptible,
+        })
+    }
+
+    // Tiles of `activity` that are active, as (tx, ty)
+    fn active(activity: &Activity) -> Vec<(usize, usize)> {
+        (0..activity.h)
+            .flat_map(|ty| (0..activity.w).map(move |tx| (tx, ty)))
+            .filter(|&(tx, ty)| activity.is_active(tx * SIZE, ty * SIZE))
+            .collect()
+    }
+
+    #[test]
+    fn wakes_the_tiles_around_a_change() {
+        let prev = Grid::new(4 * SIZE, 4 * SIZE, Cell::default());
+        let mut next = prev.clone();
+        next[(SIZE + 3, SIZE + 5)] = alive();
+        let activity = Activity::track(&prev, &next, 1, true);
+        let expected: Vec<_> = (0..3)
+            .flat_map(|ty| (0..3).map(move |tx| (tx, ty)))
+            .collect();
+        assert_eq!(active(&activity), expected);
+        assert_eq!(activity.count(), (9, 16));
+
+        // Nothing changed, nothing to do
+        assert_eq!(Activity::track(&next, &next, 1, true).count(), (0, 16));
+    }
+
+    #[test]
+    fn reaches_further_with_a_wider_radius() {
+        let prev = Grid::new(5 * SIZE, SIZE, Cell::default());
+        let mut next = prev.clone();
+        next[(2 * SIZE, 0)] = alive();
+        let near = Activity::track(&prev, &next, SIZE, true);
+        assert_eq!(active(&near), [(1, 0), (2, 0), (3, 0)]);
+        let far = Activity::track(&prev, &next, SIZE + 1, true);
+        assert_eq!(active(&far), [(0, 0), (1, 0), (2, 0), (3, 0), (4, 0)]);
+    }
+
+    #[test]
+    fn covers_partial_tiles_at_the_grid_edge() {
+        let prev = Grid::new(SIZE + 5, SIZE + 1, Cell::default());
+        let mut next = prev.clone();
+        next[(SIZE + 4, SIZE)] = alive();
+        let activity = Activity::track(&prev, &next, 1, true);
+        assert_eq!(activity.count(), (4, 4));
+        assert!(activity.is_active(SIZE + 4, SIZE));
+    }
+
+    #[test]
+    fn tracks_life_instead_of_changes() {
+        let mut map = Grid::new(3 * SIZE, SIZE, Cell::default());
+        map[(0, 0)] = alive();
+        assert_eq!(Activity::track(&map, &map, 1, true).count(), (0, 3));
+        let activity = Activity::track(&map, &map, 1, false);
+        assert_eq!(active(&activity), [(0, 0), (1, 0)]);
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::game::GameOfLife;
+use crate::rule::LifeRule;
+
+// Every case starts from the same map so reports can be compared
+pub const SEED: u64 = 42;
+
+// Generations stepped before timing starts
+const WARMUP: usize = 5;
+
+// Runs of a case are repeated until this much time has passed and the fastest
+// is kept, so timer resolution and noise don't dominate fast cases
+const MIN_TIME: Duration = Duration::from_millis(250);
+
+// Engine a benchmark case runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    // Predator-prey rules on the bounded grid
+    Cells,
+    // Predator-prey rules on the tiled infinite plane
+    Infinite,
+    // B3/S23 on the bit-packed grid
+    Bits,
+    // B3/S23 on the HashLife universe
+    Hashlife,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 4] = [
+        Backend::Cells,
+        Backend::Infinite,
+        Backend::Bits,
+        Backend::Hashlife,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Backend::Cells => "cells",
+            Backend::Infinite => "infinite",
+            Backend::Bits => "bits",
+            Backend::Hashlife => "hashlife",
+        }
+    }
+
+    // The B/S engines only count the 8 surrounding cells
+    fn supports(&self, radius: usize) -> bool {
+        match self {
+            Backend::Cells | Backend::Infinite => true,
+            Backend::Bits | Backend::Hashlife => radius == 1,
+        }
+    }
+}
+
+// One point of the benchmark matrix
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Case {
+    pub backend: Backend,
+    pub w: usize,
+    pub h: usize,
+    pub radius: usize,
+    // Cluster density of the generated map
+    pub density: f64,
+}
+
+impl Case {
+    pub fn name(&self) -> String {
+        format!(
+            "{}/{}x{}/r{}/d{}",
+            self.backend.label(),
+            self.w,
+            self.h,
+            self.radius,
+            self.density
+        )
+    }
+
+    pub fn config(&self) -> Config {
+        let life = matches!(self.backend, Backend::Bits | Backend::Hashlife);
+        Config {
+            w: self.w,
+            h: self.h,
+            radius: self.radius,
+            population: self.w * self.h / 16,
+            cluster_size: 2,
+            cluster_density: self.density,
+            info: false,
+            // The default history, its snapshots are part of every real run
+            threads: 1,
+            seed: SEED,
+            rule: life.then_some(LifeRule::CONWAY),
+            hashlife: self.backend == Backend::Hashlife,
+            infinite: self.backend == Backend::Infinite,
+            // Mutations colonise a new ring of tiles every generation, which
+            // would measure how fast the world grows rather than the engine
+            mutation: if self.backend == Backend::Infinite {
+                0.0
+            } else {
+                Config::default().mutation
+            },
+            ..Config::default()
+        }
+        .with_offsets()
+    }
+}
+
+// Every supported combination of grid size, radius and density for `backends`,
+// `quick` keeps to the smallest grid
+pub fn matrix(backends: &[Backend], quick: bool) -> Vec<Case> {
+    let sizes: &[(usize, usize)] = if quick {
+        &[(64, 64)]
+    } else {
+        &[(64, 64), (256, 128), (512, 256)]
+    };
+    let mut cases = Vec::new();
+    for &backend in backends {
+        for &(w, h) in sizes {
+            for radius in [1, 3, 5] {
+                for density in [0.3, 0.7] {
+                    if backend.supports(radius) {
+                        cases.push(Case {
+                            backend,
+                            w,
+                            h,
+                            radius,
+                            density,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    cases
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Measurement {
+    pub name: String,
+    #[serde(flatten)]
+    pub case: Case,
+    pub generations: usize,
+    pub seconds: f64,
+    pub gens_per_sec: f64,
+}
+
+// Times `generations` generations of `case` after a short warm up
+pub fn measure(case: Case, generations: usize) -> Measurement {
+    let started = Instant::now();
+    let mut seconds = f64::INFINITY;
+    while seconds.is_infinite() || started.elapsed() < MIN_TIME {
+        let mut game = GameOfLife::new(case.config());
+        for _ in 0..WARMUP {
+            game.step();
+        }
+
+        let first = game.generation();
+        let start = Instant::now();
+        while game.generation() - first < generations {
+            game.step();
+        }
+        seconds = seconds.min(start.elapsed().as_secs_f64());
+    }
+    Measurement {
+        name: case.name(),
+        case,
+        generations,
+        seconds,
+        gens_per_sec: generations as f64 / seconds,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Report {
+    pub version: String,
+    pub seed: u64,
+    pub results: Vec<Measurement>,
+}
+
+impl Report {
+    // Measures every case in order, handing each result to `progress` as it finishes
+    pub fn run(cases: &[Case], generations: usize, mut progress: impl FnMut(&Measurement)) -> Self {
+        let mut results = Vec::new();
+        for &case in cases {
+            let measurement = measure(case, generations);
+            progress(&measurement);
+            results.push(measurement);
+        }
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            seed: SEED,
+            results,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = fs::File::open(path)
+            .with_context(|| format!("failed to open report {}", path.display()))?;
+        serde_json::from_reader(file)
+            .with_context(|| format!("failed to parse report {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let file = fs::File::create(path)
+            .with_context(|| format!("failed to create report {}", path.display()))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    // Speed of every case that is also in `baseline`
+    pub fn compare(&self, baseline: &Report) -> Vec<Change> {
+        self.results
+            .iter()
+            .filter_map(|current| {
+                let before = baseline.results.iter().find(|m| m.name == current.name)?;
+                Some(Change {
+                    name: current.name.clone(),
+                    baseline: before.gens_per_sec,
+                    current: current.gens_per_sec,
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub name: String,
+    pub baseline: f64,
+    pub current: f64,
+}
+
+impl Change {
+    // Change in generations per second, negative when slower
+    pub fn percent(&self) -> f64 {
+        (self.current / self.baseline - 1.0) * 100.0
+    }
+
+    pub fn is_regression(&self, threshold: f64) -> bool {
+        self.percent() < -threshold
+    }
+}
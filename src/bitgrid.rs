@@ -0,0 +1,160 @@
+use crate::cell::CellType;
+use crate::rule::LifeRule;
+use crate::stats::{Census, Transition};
+
+// Single species grid with 64 cells per word, stepped with bit-sliced adders.
+// Bit `x % 64` of word `x / 64` in a row is the cell in column `x`; cells
+// outside the grid are dead, like in `count_neighbours`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BitGrid {
+    w: usize,
+    h: usize,
+    words: usize,
+    bits: Vec<u64>,
+}
+
+impl BitGrid {
+    pub fn new(w: usize, h: usize) -> Self {
+        let words = w.div_ceil(64);
+        Self {
+            w,
+            h,
+            words,
+            bits: vec![0; words * h],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        x < self.w && y < self.h && self.bits[y * self.words + x / 64] & (1 << (x % 64)) != 0
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, alive: bool) {
+        if x >= self.w || y >= self.h {
+            return;
+        }
+        let word = &mut self.bits[y * self.words + x / 64];
+        if alive {
+            *word |= 1 << (x % 64);
+        } else {
+            *word &= !(1 << (x % 64));
+        }
+    }
+
+    pub fn population(&self) -> usize {
+        self.bits.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    // Writes the next generation into `next` and returns the transitions
+    pub fn step(&self, rule: LifeRule, next: &mut BitGrid) -> Census {
+        if next.w != self.w || next.h != self.h {
+            *next = BitGrid::new(self.w, self.h);
+        }
+
+        // Masks of the counts that lead to a birth, survival or an early death
+        let births: Vec<usize> = (0..=8).filter(|&n| rule.births(n)).collect();
+        let survivals: Vec<usize> = (0..=8).filter(|&n| rule.survives(n)).collect();
+        let min_survival = rule.min_survival();
+
+        let mut born = 0;
+        let mut survived = 0;
+        let mut isolated = 0;
+        let mut overcrowded = 0;
+
+        let empty = vec![0; self.words];
+        for y in 0..self.h {
+            let up = if y > 0 { self.row(y - 1) } else { &empty };
+            let mid = self.row(y);
+            let down = if y + 1 < self.h {
+                self.row(y + 1)
+            } else {
+                &empty
+            };
+
+            for i in 0..self.words {
+                let mut count = [0u64; 4];
+                for row in [up, down] {
+                    add(&mut count, west(row, i));
+                    add(&mut count, row[i]);
+                    add(&mut count, east(row, i));
+                }
+                add(&mut count, west(mid, i));
+                add(&mut count, east(mid, i));
+
+                let birth = births.iter().fold(0, |m, &n| m | equals(&count, n));
+                let survive = survivals.iter().fold(0, |m, &n| m | equals(&count, n));
+                let below = (0..min_survival.min(9)).fold(0, |m, n| m | equals(&count, n));
+
+                let alive = mid[i];
+                let cells = (alive & survive) | (!alive & birth);
+                let cells = cells & self.tail_mask(i);
+                next.bits[y * self.words + i] = cells;
+
+                let died = alive & !cells;
+                born += (!alive & cells).count_ones() as usize;
+                survived += (alive & cells).count_ones() as usize;
+                isolated += (died & below).count_ones() as usize;
+                overcrowded += (died & !below).count_ones() as usize;
+            }
+        }
+
+        let mut census = Census::default();
+        census.add(CellType::PREY, Transition::Born, born);
+        census.add(CellType::PREY, Transition::Survived, survived);
+        census.add(CellType::PREY, Transition::Isolated, isolated);
+        census.add(CellType::PREY, Transition::Overcrowded, overcrowded);
+        census
+    }
+
+    fn row(&self, y: usize) -> &[u64] {
+        &self.bits[y * self.words..(y + 1) * self.words]
+    }
+
+    // Clears the padding bits past the right edge in the last word of a row
+    fn tail_mask(&self, i: usize) -> u64 {
+        let used = self.w - i * 64;
+        if used >= 64 {
+            u64::MAX
+        } else {
+            (1 << used) - 1
+        }
+    }
+}
+
+// Bit x of the result is the cell at x - 1
+fn west(row: &[u64], i: usize) -> u64 {
+    let carry = if i > 0 { row[i - 1] >> 63 } else { 0 };
+    (row[i] << 1) | carry
+}
+
+// Bit x of the result is the cell at x + 1
+fn east(row: &[u64], i: usize) -> u64 {
+    let carry = if i + 1 < row.len() {
+        row[i + 1] << 63
+    } else {
+        0
+    };
+    (row[i] >> 1) | carry
+}
+
+// Adds one bit per lane to a 4 bit counter stored as bit planes
+fn add(count: &mut [u64; 4], mut carry: u64) {
+    for plane in count.iter_mut() {
+        let next = *plane & carry;
+        *plane ^= carry;
+        carry = next;
+    }
+}
+
+// Lanes whose counter equals `n`
+fn equals(count: &[u64; 4], n: usize) -> u64 {
+    count
+        .iter()
+        .enumerate()
+        .fold(u64::MAX, |mask, (bit, plane)| {
+            if n & (1 << bit) != 0 {
+                mask & plane
+            } else {
+                mask & !plane
+            }
+        })
+}
@@ -0,0 +1,183 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    thread::sleep,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context, Result};
+use crossterm::{cursor, terminal, QueueableCommand};
+use serde::{Deserialize, Serialize};
+
+// Header line of an asciinema v2 cast file
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    version: u8,
+    width: usize,
+    height: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+}
+
+// Writes every rendered frame to an asciinema v2 cast
+pub struct CastRecording {
+    out: BufWriter<File>,
+    start: Instant,
+}
+
+impl CastRecording {
+    pub fn create(path: &Path, w: usize, h: usize) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+        let mut out = BufWriter::new(file);
+        let header = Header {
+            version: 2,
+            width: w,
+            // The info panel and the trailing newline need one more row
+            height: h + 1,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|t| t.as_secs()),
+        };
+        serde_json::to_writer(&mut out, &header)?;
+        out.write_all(b"\n")?;
+
+        let mut recording = Self {
+            out,
+            start: Instant::now(),
+        };
+        // Start from a clean screen with the cursor hidden, like the live run
+        let mut setup = Vec::new();
+        setup
+            .queue(terminal::Clear(terminal::ClearType::All))?
+            .queue(cursor::Hide)?;
+        recording.record(&setup)?;
+        Ok(recording)
+    }
+
+    pub fn record(&mut self, frame: &[u8]) -> Result<()> {
+        let time = self.start.elapsed().as_secs_f64();
+        let data = String::from_utf8_lossy(frame);
+        serde_json::to_writer(&mut self.out, &(time, "o", data))?;
+        self.out.write_all(b"\n")?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+// Plays a recorded cast back to the terminal, `speed` > 1 plays faster
+pub fn play(path: &Path, speed: f64) -> Result<()> {
+    play_to(path, speed, &mut io::stdout())
+}
+
+fn play_to(path: &Path, speed: f64, out: &mut impl Write) -> Result<()> {
+    if !(speed > 0.0 && speed.is_finite()) {
+        bail!("replay speed must be a positive number");
+    }
+    let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let mut lines = BufReader::new(file).lines();
+
+    let header = lines.next().context("cast file is empty")??;
+    let header: Header = serde_json::from_str(&header).context("invalid cast header")?;
+    if header.version != 2 {
+        bail!("unsupported asciicast version {}", header.version);
+    }
+
+    let start = Instant::now();
+    // Events listed before an earlier one play right away
+    let mut last = 0.0;
+    for line in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (time, kind, data): (f64, String, String) =
+            serde_json::from_str(&line).context("invalid cast event")?;
+        if !(time >= 0.0 && time.is_finite()) {
+            bail!("cast event at invalid time {time}");
+        }
+        if kind != "o" {
+            continue;
+        }
+
+        last = time.max(last);
+        let due = Duration::from_secs_f64(last / speed);
+        let elapsed = start.elapsed();
+        if due > elapsed {
+            sleep(due - elapsed);
+        }
+        out.write_all(data.as_bytes())?;
+        out.flush()?;
+    }
+
+    out.queue(cursor::Show)?;
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cast_file(name: &str, events: &[&str]) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("gameoflife-{}-{name}.cast", std::process::id()));
+        let mut text = String::from("{\"version\":2,\"width\":4,\"height\":2}\n");
+        for event in events {
+            text.push_str(event);
+            text.push('\n');
+        }
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn recording_plays_back_its_frames() {
+        let path =
+            std::env::temp_dir().join(format!("gameoflife-{}-record.cast", std::process::id()));
+        let mut recording = CastRecording::create(&path, 4, 2).unwrap();
+        recording.record(b"first").unwrap();
+        recording.record(b"second").unwrap();
+        drop(recording);
+
+        let mut out = Vec::new();
+        play_to(&path, 1000.0, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let first = out.find("first").unwrap();
+        assert!(out[first..].contains("second"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_speeds() {
+        let path = cast_file("speed", &[r#"[0.0, "o", "x"]"#]);
+        for speed in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(play_to(&path, speed, &mut Vec::new()).is_err());
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_negative_event_times() {
+        let path = cast_file("negative", &[r#"[-1.0, "o", "x"]"#]);
+        assert!(play_to(&path, 1.0, &mut Vec::new()).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn plays_out_of_order_events_right_away() {
+        let path = cast_file(
+            "order",
+            &[
+                r#"[0.02, "o", "a"]"#,
+                r#"[0.01, "o", "b"]"#,
+                r#"[0.0, "i", "c"]"#,
+            ],
+        );
+        let mut out = Vec::new();
+        play_to(&path, 1.0, &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with("ab"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
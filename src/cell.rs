@@ -1,86 +1,325 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::species::MAX_SPECIES;
+
 #[derive(Debug, Clone, Copy)]
 pub enum CellState {
-    Alive(usize),
+    // Energy is only used by species that eat others, the rest always have 0
+    Alive {
+        age: usize,
+        energy: usize,
+        health: Health,
+    },
     Dead,
 }
 
-#[derive(Debug, Clone, Copy)]
-pub enum CellType {
-    Prey,
-    Predetor,
+// Where a live cell is in the optional epidemic, see `Config::infection`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Health {
+    #[default]
+    Susceptible,
+    Infected,
+    Recovered,
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct Cell {
-    pub x: usize,
-    pub y: usize,
-    pub state: CellState,
-    pub kind: CellType,
+// Index of a cell's species in the run's `FoodWeb`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "KindRepr", into = "u8")]
+pub struct CellType(pub u8);
+
+impl CellType {
+    // The two species of the default food web
+    pub const PREY: CellType = CellType(0);
+    pub const PREDETOR: CellType = CellType(1);
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
 }
 
-impl Cell {
-    pub fn new(x: usize, y: usize, state: CellState, kind: CellType) -> Self {
-        Self { x, y, state, kind }
+// Event logs of older versions name the two original species
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KindRepr {
+    Index(u8),
+    Name(String),
+}
+
+impl TryFrom<KindRepr> for CellType {
+    type Error = String;
+
+    fn try_from(repr: KindRepr) -> Result<Self, Self::Error> {
+        match repr {
+            KindRepr::Index(i) if (i as usize) < MAX_SPECIES => Ok(CellType(i)),
+            KindRepr::Name(name) if name == "prey" => Ok(CellType::PREY),
+            KindRepr::Name(name) if name == "predetor" => Ok(CellType::PREDETOR),
+            KindRepr::Index(i) => Err(format!("no species {i}")),
+            KindRepr::Name(name) => Err(format!("unknown species '{name}'")),
+        }
     }
+}
+
+impl From<CellType> for u8 {
+    fn from(kind: CellType) -> u8 {
+        kind.0
+    }
+}
+
+// Heritable traits of a live cell, used instead of the matching `Config`
+// values for it. Each is limited to the bits it is packed into.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Genome {
+    pub max_age: usize,
+    // Chance of breeding in a generation, in 255ths
+    pub reproduction: usize,
+    pub resistence: usize,
+    pub aging_rate: usize,
+}
 
-    pub fn prey(x: usize, y: usize, state: CellState) -> Self {
+impl Genome {
+    pub const MAX_AGE: usize = (1 << 12) - 1;
+    const MAX_REPRODUCTION: usize = (1 << 8) - 1;
+    const MAX_RESISTENCE: usize = (1 << 6) - 1;
+    const MAX_AGING_RATE: usize = (1 << 6) - 1;
+
+    pub fn new(max_age: usize, reproduction: f64, resistence: usize, aging_rate: usize) -> Self {
         Self {
-            x,
-            y,
-            state,
-            kind: CellType::Prey,
+            max_age: max_age.clamp(1, Genome::MAX_AGE),
+            reproduction: (reproduction.clamp(0.0, 1.0) * Genome::MAX_REPRODUCTION as f64).round()
+                as usize,
+            resistence: resistence.min(Genome::MAX_RESISTENCE),
+            aging_rate: aging_rate.min(Genome::MAX_AGING_RATE),
+        }
+    }
+
+    pub fn reproduction_chance(&self) -> f64 {
+        self.reproduction as f64 / Genome::MAX_REPRODUCTION as f64
+    }
+
+    // Takes each trait from one of the parents at random
+    pub fn cross(&self, other: &Genome, rng: &mut impl Rng) -> Genome {
+        let mut pick = |a: usize, b: usize| if rng.gen_bool(0.5) { a } else { b };
+        Genome {
+            max_age: pick(self.max_age, other.max_age),
+            reproduction: pick(self.reproduction, other.reproduction),
+            resistence: pick(self.resistence, other.resistence),
+            aging_rate: pick(self.aging_rate, other.aging_rate),
+        }
+    }
+
+    // Each trait changes with chance `rate` by a tenth of its value, at least 1,
+    // up or down
+    pub fn mutate(&self, rate: f64, rng: &mut impl Rng) -> Genome {
+        let mut shift = |value: usize, min: usize, max: usize| {
+            if !rng.gen_bool(rate) {
+                return value;
+            }
+            let step = (value / 10).max(1);
+            if rng.gen_bool(0.5) {
+                (value + step).min(max)
+            } else {
+                value.saturating_sub(step).max(min)
+            }
+        };
+        Genome {
+            max_age: shift(self.max_age, 1, Genome::MAX_AGE),
+            reproduction: shift(self.reproduction, 0, Genome::MAX_REPRODUCTION),
+            resistence: shift(self.resistence, 0, Genome::MAX_RESISTENCE),
+            aging_rate: shift(self.aging_rate, 0, Genome::MAX_AGING_RATE),
         }
     }
 
-    pub fn prededator(x: usize, y: usize, state: CellState) -> Self {
+    fn pack(&self) -> u64 {
+        self.max_age as u64
+            | (self.reproduction as u64) << 12
+            | (self.resistence as u64) << 20
+            | (self.aging_rate as u64) << 26
+    }
+
+    fn unpack(bits: u64) -> Self {
         Self {
-            x,
-            y,
-            state,
-            kind: CellType::Predetor,
+            max_age: (bits & Genome::MAX_AGE as u64) as usize,
+            reproduction: (bits >> 12 & Genome::MAX_REPRODUCTION as u64) as usize,
+            resistence: (bits >> 20 & Genome::MAX_RESISTENCE as u64) as usize,
+            aging_rate: (bits >> 26 & Genome::MAX_AGING_RATE as u64) as usize,
         }
     }
 }
 
+// Packed into 64 bits: the high 32 are the genome, then 4 bits of species, 12
+// of energy, 2 of health and 14 of age. An age of 0 means the cell is dead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell(u64);
+
+impl Cell {
+    const HEALTH_SHIFT: u32 = 14;
+    const ENERGY_SHIFT: u32 = 16;
+    const KIND_SHIFT: u32 = 28;
+    const GENOME_SHIFT: u32 = 32;
+    pub const MAX_AGE: usize = (1 << Cell::HEALTH_SHIFT) - 1;
+    pub const MAX_ENERGY: usize = (1 << (Cell::KIND_SHIFT - Cell::ENERGY_SHIFT)) - 1;
+
+    pub fn new(state: CellState, kind: CellType) -> Self {
+        let bits = match state {
+            CellState::Alive {
+                age,
+                energy,
+                health,
+            } => {
+                age.clamp(1, Cell::MAX_AGE) as u64
+                    | (health as u64) << Cell::HEALTH_SHIFT
+                    | (energy.min(Cell::MAX_ENERGY) as u64) << Cell::ENERGY_SHIFT
+            }
+            CellState::Dead => 0,
+        };
+        Self((kind.0 as u64) << Cell::KIND_SHIFT | bits)
+    }
+
+    pub fn with_genome(self, genome: Genome) -> Self {
+        let state = self.0 & ((1 << Cell::GENOME_SHIFT) - 1);
+        Self(state | genome.pack() << Cell::GENOME_SHIFT)
+    }
+
+    pub fn genome(&self) -> Genome {
+        Genome::unpack(self.0 >> Cell::GENOME_SHIFT)
+    }
+
+    // The same cell in `state`, keeping its species and genome
+    pub fn with_state(self, state: CellState) -> Self {
+        Cell::new(state, self.kind()).with_genome(self.genome())
+    }
+
+    // The same cell with `health`, if it is alive
+    pub fn with_health(self, health: Health) -> Self {
+        match self.state() {
+            CellState::Alive { age, energy, .. } => self.with_state(CellState::Alive {
+                age,
+                energy,
+                health,
+            }),
+            CellState::Dead => self,
+        }
+    }
+
+    pub fn prey(state: CellState) -> Self {
+        Cell::new(state, CellType::PREY)
+    }
+
+    pub fn state(&self) -> CellState {
+        match self.age() {
+            0 => CellState::Dead,
+            age => CellState::Alive {
+                age,
+                energy: (self.0 >> Cell::ENERGY_SHIFT) as usize & Cell::MAX_ENERGY,
+                health: match self.0 >> Cell::HEALTH_SHIFT & 0b11 {
+                    0 => Health::Susceptible,
+                    1 => Health::Infected,
+                    _ => Health::Recovered,
+                },
+            },
+        }
+    }
+
+    fn age(&self) -> usize {
+        (self.0 as usize) & Cell::MAX_AGE
+    }
+
+    pub fn kind(&self) -> CellType {
+        CellType((self.0 >> Cell::KIND_SHIFT) as u8 & (MAX_SPECIES as u8 - 1))
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.age() != 0
+    }
+}
+
 impl Default for Cell {
     fn default() -> Self {
-        Self {
-            x: 0,
-            y: 0,
-            state: CellState::Dead,
-            kind: CellType::Prey,
-        }
+        Cell::new(CellState::Dead, CellType::PREY)
     }
 }
 
-impl std::fmt::Display for Cell {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self.state {
-            CellState::Dead => write!(f, " "),
-            CellState::Alive(_) => match self.kind {
-                CellType::Prey => write!(f, "◈"),
-                CellType::Predetor => write!(f, "{}", '¤'),
-            }, // CellState::Alive(_) => write!(f, "{}", '¤'),
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    const MAX: Genome = Genome {
+        max_age: Genome::MAX_AGE,
+        reproduction: Genome::MAX_REPRODUCTION,
+        resistence: Genome::MAX_RESISTENCE,
+        aging_rate: Genome::MAX_AGING_RATE,
+    };
+
+    #[test]
+    fn genomes_and_states_survive_packing() {
+        let state = CellState::Alive {
+            age: Cell::MAX_AGE,
+            energy: Cell::MAX_ENERGY,
+            health: Health::Recovered,
+        };
+        for genome in [Genome::default(), Genome::new(100, 0.1, 1, 5), MAX] {
+            assert_eq!(Genome::unpack(genome.pack()), genome);
+            let cell = Cell::new(state, CellType(MAX_SPECIES as u8 - 1)).with_genome(genome);
+            assert_eq!(cell.genome(), genome);
+            assert_eq!(cell.kind(), CellType(MAX_SPECIES as u8 - 1));
+            assert!(matches!(
+                cell.state(),
+                CellState::Alive {
+                    age: Cell::MAX_AGE,
+                    energy: Cell::MAX_ENERGY,
+                    health: Health::Recovered,
+                }
+            ));
         }
     }
-}
 
-// static GLYPHSS: [char; 175] = [
-//     'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O', 'P', 'Q', 'R', 'S',
-//     'T', 'U', 'V', 'W', 'X', 'Y', 'Z', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l',
-//     'm', 'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', // Numbers
-//     '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', // Special Characters
-//     '@', '#', '$', '%', '&', '*', '+', '-', '=', '~', '`', '^', '\'', '"', '\\', '/', '|', '_',
-//     ':', ';', '.', ',', '<', '>', '?', '[', ']', '{', '}', '(', ')', '!', '¤', '÷', '×', '·',
-//     '°', // Unicode Symbols
-//     '░', '▒', '▓', '█', '▀', '▄', '■', '□', '≡', '≠', '≤', '≥', '∞', '∴', '∵', '⌐', '¬', '±', '∑',
-//     '∆', '∇', 'π', 'λ', 'θ', '∅', 'µ', 'ξ', 'β', 'Ω', 'Σ', 'Ξ', 'Ψ', '★', '☆', '☀', '☼', '☽', '☾',
-//     '☁', '⚡', '❄', '❇', '✦', '✧', '✩', '✪', '✫', '✬', '✭', '✮', '✯', '☢', '☣', '☤', '☥', '☦', '☧',
-//     '☨', '☩', '☪', '☫', '☬', '☭', '☯', '☸', '☹', '☺', '☻', '♠', '♣', '♥', '♦', '♤', '♧', '♡', '♢',
-// ];
-//
-// fn random_glyph() -> char {
-//     let mut rng = rand::thread_rng();
-//     let idx = rng.gen_range(0..175);
-//     GLYPHSS[idx]
-// }
+    #[test]
+    fn new_genomes_are_clamped_to_their_bits() {
+        assert_eq!(Genome::new(usize::MAX, 2.0, usize::MAX, usize::MAX), MAX);
+        assert_eq!(Genome::new(0, -1.0, 0, 0).max_age, 1);
+        assert_eq!(Genome::new(10, 1.0, 0, 0).reproduction_chance(), 1.0);
+    }
+
+    #[test]
+    fn crossing_takes_each_trait_from_a_parent() {
+        let a = Genome::new(100, 0.2, 1, 5);
+        let b = Genome::new(300, 0.8, 7, 9);
+        let mut rng = StdRng::seed_from_u64(1);
+        let children: Vec<_> = (0..64).map(|_| a.cross(&b, &mut rng)).collect();
+        for child in &children {
+            assert!([a.max_age, b.max_age].contains(&child.max_age));
+            assert!([a.reproduction, b.reproduction].contains(&child.reproduction));
+            assert!([a.resistence, b.resistence].contains(&child.resistence));
+            assert!([a.aging_rate, b.aging_rate].contains(&child.aging_rate));
+        }
+        assert!(children.iter().any(|child| child.max_age == a.max_age));
+        assert!(children.iter().any(|child| child.max_age == b.max_age));
+        assert_eq!(a.cross(&a, &mut rng), a);
+    }
+
+    #[test]
+    fn mutations_move_each_trait_a_tenth() {
+        // A reproduction of 0.5 is 128 255ths
+        let genome = Genome::new(100, 0.5, 0, 5);
+        let mut rng = StdRng::seed_from_u64(2);
+        assert_eq!(genome.mutate(0.0, &mut rng), genome);
+        for _ in 0..64 {
+            let mutant = genome.mutate(1.0, &mut rng);
+            assert!([90, 110].contains(&mutant.max_age));
+            assert!([116, 140].contains(&mutant.reproduction));
+            assert!([0, 1].contains(&mutant.resistence));
+            assert!([4, 6].contains(&mutant.aging_rate));
+        }
+
+        // Never past the bits a trait is packed into
+        let low = Genome::new(1, 0.0, 0, 0);
+        for _ in 0..64 {
+            let (high, low) = (MAX.mutate(1.0, &mut rng), low.mutate(1.0, &mut rng));
+            assert_eq!(Genome::unpack(high.pack()), high);
+            assert!(low.max_age >= 1);
+        }
+    }
+}
@@ -1,16 +1,47 @@
+use crate::cell::Genome;
 use crate::game::generate_ring_offsets;
+use crate::rule::LifeRule;
+use crate::schedule::{self, Schedule};
+use crate::species::{self, FoodWeb};
+use crate::terrain::{self, Terrain};
+use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy)]
+use anyhow::bail;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+// Fields missing from logs of older versions take their default
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub w: usize,
     pub h: usize,
     pub population: usize,
+    // Frames per second, 0 runs uncapped
     pub fps: usize,
+    // Steps simulated between two rendered frames
+    #[serde(default)]
+    pub frame_skip: FrameSkip,
+    pub radius: usize,
+    #[serde(default)]
+    pub shape: Shape,
+    // Derived from `radius` and `shape`, rebuilt with `with_offsets` after loading
+    #[serde(skip)]
     pub offsets: &'static [(isize, isize)],
     pub cluster_size: usize,
     pub cluster_density: f64,
     pub info: bool,
+    // Past generations stepping back re-simulates from a snapshot for, one
+    // every `history::INTERVAL` steps
+    #[serde(default)]
+    pub history: usize,
+    // Worker threads for the simulation step, 0 uses every core
+    #[serde(default)]
+    pub threads: usize,
     pub reproduction: usize,
+    // Chance of a live cell breeding in a generation, the genome of cells
+    // without parents starts from it
+    pub reproduction_chance: f64,
     pub overpopulation: usize,
     pub underpopulation: usize,
     pub survivability: usize,
@@ -19,19 +50,81 @@ pub struct Config {
     pub resistence: usize,
     pub aging_rate: usize,
     pub predetor_rate: f64,
+    // Predator energy lost every generation, gained from prey around it, needed
+    // to breed and given to a newborn
+    pub energy_loss: usize,
+    pub energy_gain: usize,
+    pub breed_energy: usize,
+    pub birth_energy: usize,
+    // Predators that can breed put their newborn into the cell of the prey they eat
+    pub offspring: bool,
+    // Chance that a caught prey gets away anyway, see `predation::hunt`
+    pub escape: f64,
+    // Predators move toward prey and prey away from predators after each step
+    pub movement: bool,
+    // Chance of each trait of a newborn's genome changing, see `Genome::mutate`
+    pub gene_mutation: f64,
+    // Resource layer prey graze on, see `Resources`. Without it prey breed
+    // from nothing.
+    pub resources: bool,
+    pub grazing: f64,
+    pub diffusion: f64,
+    pub regrowth: f64,
+    // How many times more likely prey breed on fertile ground
+    pub fertility: f64,
+    // Epidemic among the live cells, off at an infection chance of 0. Chance
+    // of catching it from each infected neighbour per generation, scaled by
+    // `cross_infection` for other species; chances per generation of an
+    // infected cell recovering for good or dying of it; extra aging while
+    // infected; and the share of the first cells that start infected.
+    pub infection: f64,
+    pub cross_infection: f64,
+    pub recovery: f64,
+    pub virulence: f64,
+    pub sick_aging: usize,
+    pub infected: f64,
+    // Species of the run and who eats whom, see `FoodWeb`
+    #[serde(with = "leaked")]
+    pub web: &'static FoodWeb,
+    // Ground under the map, open everywhere unless loaded, see `with_terrain`
+    #[serde(with = "leaked")]
+    pub terrain: &'static Terrain,
+    // Seasons and scheduled events, see `Schedule`
+    #[serde(with = "leaked")]
+    pub schedule: &'static Schedule,
+    pub seed: u64,
+    // Two-state B/S rule replacing the predator-prey rules
+    #[serde(default)]
+    pub rule: Option<LifeRule>,
+    // Run `rule` on an unbounded HashLife universe, 2^`step_exp` generations a step
+    #[serde(default)]
+    pub hashlife: bool,
+    #[serde(default)]
+    pub step_exp: u8,
+    // Simulate an unbounded tiled plane with the viewport following `follow`
+    #[serde(default)]
+    pub infinite: bool,
+    #[serde(default)]
+    pub follow: Follow,
 }
 
 impl Config {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         w: Option<usize>,
         h: Option<usize>,
         population: usize,
         fps: usize,
+        frame_skip: FrameSkip,
         radius: usize,
+        shape: Shape,
         cluster_size: usize,
         cluster_density: f64,
         info: bool,
+        history: usize,
+        threads: usize,
         reproduction: usize,
+        reproduction_chance: f64,
         overpopulation: usize,
         underpopulation: usize,
         survivability: usize,
@@ -40,18 +133,54 @@ impl Config {
         resistence: usize,
         aging_rate: usize,
         predetor_rate: f64,
+        energy_loss: usize,
+        energy_gain: usize,
+        breed_energy: usize,
+        birth_energy: usize,
+        offspring: bool,
+        escape: f64,
+        movement: bool,
+        gene_mutation: f64,
+        resources: bool,
+        grazing: f64,
+        diffusion: f64,
+        regrowth: f64,
+        fertility: f64,
+        infection: f64,
+        cross_infection: f64,
+        recovery: f64,
+        virulence: f64,
+        sick_aging: usize,
+        infected: f64,
+        web: &'static FoodWeb,
+        schedule: &'static Schedule,
+        seed: u64,
+        rule: Option<LifeRule>,
+        hashlife: bool,
+        step_exp: u8,
+        infinite: bool,
+        follow: Follow,
     ) -> Self {
-        let offsets: &'static mut [(isize, isize)] = generate_ring_offsets(radius as isize).leak();
+        let offsets: &'static mut [(isize, isize)] =
+            generate_ring_offsets(radius as isize, shape).leak();
+        // Headless runs may not have a terminal to measure
+        let (cols, rows) = crossterm::terminal::size().unwrap_or((80, 24));
         Self {
-            w: w.unwrap_or(crossterm::terminal::size().unwrap().0.into()),
-            h: h.unwrap_or(crossterm::terminal::size().unwrap().1.into()),
+            w: w.unwrap_or(cols.into()),
+            h: h.unwrap_or(rows.into()),
             population,
             fps,
+            frame_skip,
+            radius,
+            shape,
             offsets,
             cluster_size,
             cluster_density,
             info,
+            history,
+            threads,
             reproduction,
+            reproduction_chance,
             overpopulation,
             underpopulation,
             survivability,
@@ -60,6 +189,226 @@ impl Config {
             resistence,
             aging_rate,
             predetor_rate,
+            energy_loss,
+            energy_gain,
+            breed_energy,
+            birth_energy,
+            offspring,
+            escape,
+            movement,
+            gene_mutation,
+            resources,
+            grazing,
+            diffusion,
+            regrowth,
+            fertility,
+            infection,
+            cross_infection,
+            recovery,
+            virulence,
+            sick_aging,
+            infected,
+            web,
+            terrain: terrain::open_terrain(),
+            schedule,
+            seed,
+            rule,
+            hashlife,
+            step_exp,
+            infinite,
+            follow,
+        }
+    }
+
+    // Pure B/S rules on a Moore radius 1 neighbourhood run on packed bits
+    pub fn bit_engine(&self) -> bool {
+        !self.hashlife
+            && !self.infinite
+            && self.rule.is_some()
+            && self.radius == 1
+            && self.shape == Shape::Moore
+    }
+
+    pub fn with_offsets(mut self) -> Self {
+        self.offsets = generate_ring_offsets(self.radius as isize, self.shape).leak();
+        self
+    }
+
+    // Lays `terrain` over the map, stretched to its size
+    pub fn with_terrain(mut self, terrain: &Terrain) -> Self {
+        self.terrain = Box::leak(Box::new(terrain.resize(self.w, self.h)));
+        self
+    }
+
+    // Sets `param`, clamped to what it can take
+    pub fn set(&mut self, param: Param) {
+        match param.with_value(param.value()) {
+            Param::Reproduction(value) => self.reproduction = value,
+            Param::Overpopulation(value) => self.overpopulation = value,
+            Param::Underpopulation(value) => self.underpopulation = value,
+            Param::Survivability(value) => self.survivability = value,
+            Param::Age(value) => self.age = value,
+            Param::Mutation(value) => self.mutation = value,
+            Param::Resistence(value) => self.resistence = value,
+            Param::AgingRate(value) => self.aging_rate = value,
+            Param::ReproductionChance(value) => self.reproduction_chance = value,
+        }
+    }
+}
+
+// Which cells within `radius` count as neighbours
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Shape {
+    // The full square
+    #[default]
+    Moore,
+    // Manhattan distance, a diamond
+    VonNeumann,
+    // Euclidean distance
+    Circle,
+}
+
+// Point of an infinite world the viewport is centred on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Follow {
+    // Centre of mass of the live cells
+    #[default]
+    Centroid,
+    Point(i64, i64),
+}
+
+impl FromStr for Follow {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("centroid") {
+            return Ok(Follow::Centroid);
+        }
+        let Some((x, y)) = s.split_once(',') else {
+            bail!("expected 'centroid' or a point like 10,-5, got '{s}'");
+        };
+        Ok(Follow::Point(x.trim().parse()?, y.trim().parse()?))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameSkip {
+    Fixed(usize),
+    // As many steps as fit in the frame time
+    Auto,
+}
+
+impl Default for FrameSkip {
+    fn default() -> Self {
+        FrameSkip::Fixed(1)
+    }
+}
+
+impl FromStr for FrameSkip {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(FrameSkip::Auto);
+        }
+        match s.parse() {
+            Ok(0) => bail!("at least one step per frame is needed"),
+            Ok(steps) => Ok(FrameSkip::Fixed(steps)),
+            Err(_) => bail!("expected 'auto' or a number of steps, got '{s}'"),
+        }
+    }
+}
+
+// A rule parameter that can be changed while the simulation is running
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Param {
+    Reproduction(usize),
+    Overpopulation(usize),
+    Underpopulation(usize),
+    Survivability(usize),
+    Age(usize),
+    Mutation(f64),
+    Resistence(usize),
+    AgingRate(usize),
+    ReproductionChance(f64),
+}
+
+impl Param {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Param::Reproduction(_) => "reproduction",
+            Param::Overpopulation(_) => "overpopulation",
+            Param::Underpopulation(_) => "underpopulation",
+            Param::Survivability(_) => "survivability",
+            Param::Age(_) => "age",
+            Param::Mutation(_) => "mutation",
+            Param::Resistence(_) => "resistence",
+            Param::AgingRate(_) => "aging_rate",
+            Param::ReproductionChance(_) => "reproduction_chance",
+        }
+    }
+
+    pub fn value(&self) -> f64 {
+        match *self {
+            Param::Reproduction(value)
+            | Param::Overpopulation(value)
+            | Param::Underpopulation(value)
+            | Param::Survivability(value)
+            | Param::Age(value)
+            | Param::Resistence(value)
+            | Param::AgingRate(value) => value as f64,
+            Param::Mutation(value) | Param::ReproductionChance(value) => value,
+        }
+    }
+
+    // The same parameter set to `value`, rounded for counts and clamped to
+    // what it can take
+    pub fn with_value(&self, value: f64) -> Param {
+        let count = value.round().max(0.0) as usize;
+        match self {
+            Param::Reproduction(_) => Param::Reproduction(count),
+            Param::Overpopulation(_) => Param::Overpopulation(count),
+            Param::Underpopulation(_) => Param::Underpopulation(count),
+            Param::Survivability(_) => Param::Survivability(count),
+            Param::Age(_) => Param::Age(count.clamp(1, Genome::MAX_AGE)),
+            Param::Mutation(_) => Param::Mutation(value.clamp(0.0, 1.0)),
+            Param::Resistence(_) => Param::Resistence(count),
+            Param::AgingRate(_) => Param::AgingRate(count),
+            Param::ReproductionChance(_) => Param::ReproductionChance(value.clamp(0.0, 1.0)),
+        }
+    }
+
+    // Fails for values `with_value` would have to clamp, such as chances
+    // above 1, naming the parameter
+    pub fn check(&self) -> anyhow::Result<()> {
+        let (low, high) = match self {
+            Param::Mutation(_) | Param::ReproductionChance(_) => (0.0, 1.0),
+            Param::Age(_) => (1.0, Genome::MAX_AGE as f64),
+            _ => return Ok(()),
+        };
+        let value = self.value();
+        if !(low..=high).contains(&value) {
+            bail!("{} {value} is out of range {low} to {high}", self.label());
+        }
+        Ok(())
+    }
+
+    // The same parameter with its value in `config`
+    pub fn current(&self, config: &Config) -> Param {
+        match self {
+            Param::Reproduction(_) => Param::Reproduction(config.reproduction),
+            Param::Overpopulation(_) => Param::Overpopulation(config.overpopulation),
+            Param::Underpopulation(_) => Param::Underpopulation(config.underpopulation),
+            Param::Survivability(_) => Param::Survivability(config.survivability),
+            Param::Age(_) => Param::Age(config.age),
+            Param::Mutation(_) => Param::Mutation(config.mutation),
+            Param::Resistence(_) => Param::Resistence(config.resistence),
+            Param::AgingRate(_) => Param::AgingRate(config.aging_rate),
+            Param::ReproductionChance(_) => Param::ReproductionChance(config.reproduction_chance),
         }
     }
 }
@@ -71,6 +420,9 @@ impl Default for Config {
             h: 110,
             population: 2000,
             fps: 24,
+            frame_skip: FrameSkip::Fixed(1),
+            radius: 1,
+            shape: Shape::Moore,
             offsets: &[
                 (-1, -1),
                 (-1, 0),
@@ -84,7 +436,10 @@ impl Default for Config {
             cluster_size: 10,
             cluster_density: 0.5,
             info: true,
+            history: 50,
+            threads: 1,
             reproduction: 3,
+            reproduction_chance: 0.1,
             overpopulation: 3,
             underpopulation: 2,
             survivability: 2,
@@ -93,6 +448,53 @@ impl Default for Config {
             resistence: 1,
             aging_rate: 5,
             predetor_rate: 0.01,
+            energy_loss: 1,
+            energy_gain: 3,
+            breed_energy: 12,
+            birth_energy: 6,
+            offspring: false,
+            escape: 0.01,
+            movement: false,
+            gene_mutation: 0.05,
+            resources: false,
+            grazing: 0.2,
+            diffusion: 0.1,
+            regrowth: 0.05,
+            fertility: 2.0,
+            infection: 0.0,
+            cross_infection: 0.1,
+            recovery: 0.05,
+            virulence: 0.01,
+            sick_aging: 2,
+            infected: 0.01,
+            web: species::default_web(),
+            terrain: terrain::open_terrain(),
+            schedule: schedule::no_schedule(),
+            seed: 0,
+            rule: None,
+            hashlife: false,
+            step_exp: 0,
+            infinite: false,
+            follow: Follow::Centroid,
         }
     }
 }
+
+// Stores loaded data such as a food web or terrain in a `Config`, which is
+// `Copy`. They are loaded once a run, so leaking them is cheap.
+pub mod leaked {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<T: Serialize, S: Serializer>(
+        value: &&'static T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T: Deserialize<'de>, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<&'static T, D::Error> {
+        Ok(Box::leak(Box::new(T::deserialize(deserializer)?)))
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use crate::cell::{CellType, Genome};
+use crate::config::{Config, Param};
+use crate::replay::Event;
+use anyhow::Result;
+use crossterm::{
+    cursor,
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, KeyCode, KeyEvent, KeyEventKind,
+        KeyModifiers, MouseButton, MouseEventKind,
+    },
+    terminal, QueueableCommand,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Action {
+    Quit,
+    TogglePause,
+    StepForward,
+    StepBack,
+    Apply(Event),
+}
+
+// Puts the terminal into raw mode with mouse reporting for the lifetime of the
+// guard, restoring it even when the game loop bails out with an error
+pub struct RawTerminal;
+
+impl RawTerminal {
+    pub fn enable() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        stdout.queue(EnableMouseCapture)?.queue(cursor::Hide)?;
+        stdout.flush()?;
+        Ok(Self)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        let _ = stdout
+            .queue(DisableMouseCapture)
+            .and_then(|s| s.queue(cursor::Show))
+            .and_then(|s| s.flush());
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// Drains all pending terminal events without blocking.
+//
+// Keys:   space pause, n / → step, b / ← step back, q / esc quit
+//         lower case letter lowers a rule parameter, upper case raises it,
+//         using the same letters as the short CLI flags:
+//         x reproduction, o overpopulation, u underpopulation, s survivability,
+//         a age, m mutation, t resistence, g aging rate
+// Mouse:  left click spawns the first species of the food web, right click
+//         the second, by default prey and predators
+pub fn poll(config: &Config) -> Result<Vec<Action>> {
+    let mut actions = Vec::new();
+    while event::poll(Duration::ZERO)? {
+        match event::read()? {
+            event::Event::Key(key) if key.kind != KeyEventKind::Release => {
+                if let Some(action) = key_action(key, config) {
+                    actions.push(action);
+                }
+            }
+            event::Event::Mouse(mouse) => {
+                let kind = match mouse.kind {
+                    MouseEventKind::Down(MouseButton::Left)
+                    | MouseEventKind::Drag(MouseButton::Left) => CellType::PREY,
+                    MouseEventKind::Down(MouseButton::Right)
+                    | MouseEventKind::Drag(MouseButton::Right)
+                        if config.web.len() > 1 =>
+                    {
+                        CellType::PREDETOR
+                    }
+                    _ => continue,
+                };
+                let (x, y) = (mouse.column as usize, mouse.row as usize);
+                if x < config.w && y < config.h {
+                    actions.push(Action::Apply(Event::Spawn { x, y, kind }));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(actions)
+}
+
+fn key_action(key: KeyEvent, config: &Config) -> Option<Action> {
+    let action = match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Action::Quit,
+        KeyCode::Char('q') | KeyCode::Esc => Action::Quit,
+        KeyCode::Char(' ') => Action::TogglePause,
+        KeyCode::Char('n') | KeyCode::Right => Action::StepForward,
+        KeyCode::Char('b') | KeyCode::Left => Action::StepBack,
+        KeyCode::Char(c) => Action::Apply(Event::Set(adjust(config, c)?)),
+        _ => return None,
+    };
+    Some(action)
+}
+
+fn adjust(config: &Config, key: char) -> Option<Param> {
+    let step = |value: usize| {
+        if key.is_ascii_uppercase() {
+            value + 1
+        } else {
+            value.saturating_sub(1)
+        }
+    };
+    let param = match key.to_ascii_lowercase() {
+        'x' => Param::Reproduction(step(config.reproduction)),
+        'o' => Param::Overpopulation(step(config.overpopulation)),
+        'u' => Param::Underpopulation(step(config.underpopulation)),
+        's' => Param::Survivability(step(config.survivability)),
+        'a' => Param::Age(if key.is_ascii_uppercase() {
+            (config.age + 10).min(Genome::MAX_AGE)
+        } else {
+            config.age.saturating_sub(10).max(1)
+        }),
+        'm' => Param::Mutation(if key.is_ascii_uppercase() {
+            (config.mutation * 2.0).clamp(0.0001, 1.0)
+        } else {
+            config.mutation / 2.0
+        }),
+        't' => Param::Resistence(step(config.resistence)),
+        'g' => Param::AgingRate(step(config.aging_rate)),
+        _ => return None,
+    };
+    Some(param)
+}
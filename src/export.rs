@@ -0,0 +1,268 @@
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use crossterm::style::Color;
+
+// Writes rendered generations to disk as a PNG sequence and/or an animated GIF.
+// Frames are stored as indexed images using the xterm 256 colour palette so
+// they come out with exactly the colours the terminal renderer uses.
+#[derive(Default)]
+pub struct Export {
+    png: Option<PngSequence>,
+    gif: Option<GifRecording>,
+    scale: usize,
+}
+
+struct PngSequence {
+    every: usize,
+    pattern: String,
+    frame: usize,
+}
+
+struct GifRecording {
+    encoder: gif::Encoder<BufWriter<File>>,
+    frames: usize,
+    written: usize,
+    delay: u16,
+}
+
+impl Export {
+    pub fn new(scale: usize) -> Self {
+        Self {
+            png: None,
+            gif: None,
+            scale: scale.max(1),
+        }
+    }
+
+    pub fn png_every(mut self, every: usize, pattern: String) -> Result<Self> {
+        if every == 0 {
+            bail!("--png-every needs a frame interval of at least 1");
+        }
+        self.png = Some(PngSequence {
+            every,
+            pattern,
+            frame: 0,
+        });
+        Ok(self)
+    }
+
+    pub fn gif(
+        mut self,
+        path: &Path,
+        frames: usize,
+        w: usize,
+        h: usize,
+        fps: usize,
+    ) -> Result<Self> {
+        let (width, height) = self.image_size(w, h)?;
+        create_parent(path)?;
+        let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+        let mut encoder = gif::Encoder::new(BufWriter::new(file), width, height, &palette())?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        self.gif = Some(GifRecording {
+            encoder,
+            frames,
+            written: 0,
+            // GIF delays are in hundredths of a second, uncapped runs play
+            // back as fast as most viewers allow
+            delay: 100usize.checked_div(fps).map_or(2, |delay| delay.max(1)) as u16,
+        });
+        Ok(self)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.png.is_some() || self.gif.is_some()
+    }
+
+    pub fn capture(
+        &mut self,
+        w: usize,
+        h: usize,
+        color: impl Fn(usize, usize) -> Color,
+    ) -> Result<()> {
+        if !self.is_active() {
+            return Ok(());
+        }
+        let (width, height) = self.image_size(w, h)?;
+        let pixels = self.rasterize(w, h, color);
+
+        if let Some(png) = self.png.as_mut() {
+            if png.frame % png.every == 0 {
+                let path = frame_path(&png.pattern, png.frame);
+                write_png(&path, width, height, &pixels)?;
+            }
+            png.frame += 1;
+        }
+
+        if let Some(gif) = self.gif.as_mut() {
+            let frame = gif::Frame {
+                width,
+                height,
+                delay: gif.delay,
+                buffer: pixels.into(),
+                ..gif::Frame::default()
+            };
+            gif.encoder.write_frame(&frame)?;
+            gif.written += 1;
+            // Dropping the encoder writes the GIF trailer
+            if gif.written >= gif.frames {
+                self.gif = None;
+            }
+        }
+        Ok(())
+    }
+
+    fn image_size(&self, w: usize, h: usize) -> Result<(u16, u16)> {
+        let width = u16::try_from(w * self.scale).context("image is too wide")?;
+        let height = u16::try_from(h * self.scale).context("image is too tall")?;
+        Ok((width, height))
+    }
+
+    fn rasterize(&self, w: usize, h: usize, color: impl Fn(usize, usize) -> Color) -> Vec<u8> {
+        let scale = self.scale;
+        let mut pixels = vec![0; w * scale * h * scale];
+        for y in 0..h {
+            for x in 0..w {
+                let index = palette_index(color(x, y));
+                for py in y * scale..(y + 1) * scale {
+                    let start = py * w * scale + x * scale;
+                    pixels[start..start + scale].fill(index);
+                }
+            }
+        }
+        pixels
+    }
+}
+
+fn create_parent(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+    }
+    Ok(())
+}
+
+fn write_png(path: &Path, width: u16, height: u16, pixels: &[u8]) -> Result<()> {
+    create_parent(path)?;
+    let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width.into(), height.into());
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(palette());
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+    Ok(())
+}
+
+// Expands printf style `%d` / `%06d` in the pattern with the frame number
+fn frame_path(pattern: &str, frame: usize) -> PathBuf {
+    let Some(start) = pattern.find('%') else {
+        return PathBuf::from(format!("{pattern}{frame}"));
+    };
+    let rest = &pattern[start + 1..];
+    let Some(end) = rest.find('d') else {
+        return PathBuf::from(pattern);
+    };
+    let width: usize = rest[..end].trim_start_matches('0').parse().unwrap_or(0);
+    PathBuf::from(format!(
+        "{}{:0width$}{}",
+        &pattern[..start],
+        frame,
+        &rest[end + 1..]
+    ))
+}
+
+fn palette_index(color: Color) -> u8 {
+    match color {
+        Color::AnsiValue(value) => value,
+        Color::Black => 0,
+        Color::DarkGrey => 8,
+        Color::White => 15,
+        _ => 0,
+    }
+}
+
+// The xterm 256 colour palette as packed RGB triples
+fn palette() -> Vec<u8> {
+    const BASE: [[u8; 3]; 16] = [
+        [0, 0, 0],
+        [128, 0, 0],
+        [0, 128, 0],
+        [128, 128, 0],
+        [0, 0, 128],
+        [128, 0, 128],
+        [0, 128, 128],
+        [192, 192, 192],
+        [128, 128, 128],
+        [255, 0, 0],
+        [0, 255, 0],
+        [255, 255, 0],
+        [0, 0, 255],
+        [255, 0, 255],
+        [0, 255, 255],
+        [255, 255, 255],
+    ];
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+    let mut palette = Vec::with_capacity(256 * 3);
+    for rgb in BASE {
+        palette.extend_from_slice(&rgb);
+    }
+    for i in 0..216 {
+        palette.extend_from_slice(&[LEVELS[i / 36], LEVELS[(i / 6) % 6], LEVELS[i % 6]]);
+    }
+    for i in 0..24 {
+        let grey = 8 + 10 * i as u8;
+        palette.extend_from_slice(&[grey, grey, grey]);
+    }
+    palette
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_frames_with_the_pattern_padding() {
+        assert_eq!(
+            frame_path("out/%06d.png", 42),
+            PathBuf::from("out/000042.png")
+        );
+        assert_eq!(frame_path("frame-%d.png", 7), PathBuf::from("frame-7.png"));
+        assert_eq!(frame_path("f%3d.png", 1234), PathBuf::from("f1234.png"));
+        assert_eq!(frame_path("frame", 3), PathBuf::from("frame3"));
+        assert_eq!(frame_path("100%.png", 3), PathBuf::from("100%.png"));
+    }
+
+    #[test]
+    fn palette_fits_a_gif() {
+        let palette = palette();
+        assert_eq!(palette.len() % 3, 0);
+        assert!(palette.len() / 3 <= 256);
+        assert_eq!(&palette[15 * 3..16 * 3], &[255, 255, 255]);
+        assert_eq!(&palette[196 * 3..197 * 3], &[255, 0, 0]);
+        assert_eq!(&palette[232 * 3..233 * 3], &[8, 8, 8]);
+    }
+
+    #[test]
+    fn rasterizes_each_cell_into_a_scaled_block() {
+        let colors = [
+            [Color::AnsiValue(196), Color::White],
+            [Color::Black, Color::AnsiValue(46)],
+        ];
+        let color = |x: usize, y: usize| colors[y][x];
+
+        assert_eq!(Export::new(1).rasterize(2, 2, color), [196, 15, 0, 46]);
+        #[rustfmt::skip]
+        assert_eq!(Export::new(2).rasterize(2, 2, color), [
+            196, 196, 15, 15,
+            196, 196, 15, 15,
+            0, 0, 46, 46,
+            0, 0, 46, 46,
+        ]);
+    }
+}
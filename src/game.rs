@@ -1,67 +1,208 @@
 use std::{
+    collections::HashMap,
     io::{self, Stdout, Write},
     thread::sleep,
     time::{Duration, Instant},
 };
 
-use crate::cell::{Cell, CellState, CellType};
-use crate::config::Config;
+use crate::activity::{self, Activity};
+use crate::bitgrid::BitGrid;
+use crate::cast::CastRecording;
+use crate::cell::{Cell, CellState, CellType, Genome, Health};
+use crate::config::{Config, Follow, FrameSkip, Shape};
+use crate::controls::{self, Action, RawTerminal};
+use crate::export::Export;
+use crate::grid::Grid;
+use crate::hashlife::{NodeId, Universe};
+use crate::history::{self, History};
+use crate::metrics::{Metrics, MetricsLog, Rates, Sample, SpeciesSample};
+use crate::movement::{self, Mover};
+use crate::neighbours::{Neighbours, SummedArea};
+use crate::predation::{self, Hunt, Hunter};
+use crate::replay::{Entry, Event, EventLog, EventLogWriter};
+use crate::resources::Resources;
+use crate::species::{Rules, MAX_SPECIES};
+use crate::stats::{Census, Epidemic, GenePool, Transition};
+use crate::terrain::{Ground, Terrain};
+use crate::world::{self, TileKey, World, TILE};
 use anyhow::Result;
 use crossterm::{
     cursor,
     style::{self, Color, Print, Stylize},
     QueueableCommand,
 };
-use rand::{thread_rng, Rng};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 // Game Loop (Infinite loop and frame updates)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 struct GameState {
     h: usize,
     w: usize,
-    map: Vec<Vec<Cell>>,
+    map: Grid<Cell>,
+    // Resource layer under `map`, when enabled
+    resources: Option<Resources>,
+    // Packed single species cells, used instead of `map` by the bit engine
+    bits: Option<BitGrid>,
+    // Root of the HashLife universe, used instead of `map` in HashLife mode
+    root: Option<NodeId>,
+    // Tiled plane used instead of `map` in infinite mode, with the world
+    // position of the top left corner of the screen
+    world: Option<World>,
+    view: (i64, i64),
+    // Tiles of `map` the next step has to simulate, all of them when unset
+    activity: Option<Activity>,
     offsets: &'static [(isize, isize)],
-    preys: usize,
-    predators: usize,
+    // Live cells of each species of the food web
+    population: [usize; MAX_SPECIES],
+    // Traits of the live cells, empty under B/S rules
+    genes: GenePool,
+    epidemic: Epidemic,
     generation: usize,
+    census: Census,
 }
 
 impl GameState {
     fn new(config: Config) -> Self {
-        let mut state = Self {
-            map: vec![vec![Cell::default(); config.w]; config.h],
+        let (map, bits) = if config.hashlife || config.infinite {
+            (Grid::default(), None)
+        } else if config.bit_engine() {
+            (Grid::default(), Some(BitGrid::new(config.w, config.h)))
+        } else {
+            (Grid::new(config.w, config.h, Cell::default()), None)
+        };
+        // Only the predator-prey rules on a bounded map graze
+        let bounded = !config.hashlife && !config.infinite && config.rule.is_none();
+        let resources = (config.resources && bounded).then(|| Resources::new(config.w, config.h));
+        Self {
+            map,
+            resources,
+            bits,
+            root: None,
+            world: config.infinite.then(World::default),
+            view: (0, 0),
+            activity: None,
             w: config.w,
             h: config.h,
             offsets: config.offsets,
-            preys: 0,
-            predators: 0,
+            population: [0; MAX_SPECIES],
+            genes: GenePool::default(),
+            epidemic: Epidemic::default(),
             generation: 0,
-        };
-        GameState::gen_empty_map(&mut state);
-        state
+            census: Census::default(),
+        }
     }
 
     fn init(config: Config) -> Self {
         let mut state = GameState::new(config);
-        GameState::gen_clustered_random_map(&mut state, config);
+        // HashLife universes are seeded by `GameOfLife::new`
+        if !config.hashlife {
+            GameState::gen_clustered_random_map(&mut state, config);
+        }
+        state.count_population();
+        state.follow(config.follow);
         state
     }
 
-    fn gen_empty_map(state: &mut GameState) {
-        let w = state.w;
-        let h = state.h;
-        for y in 0..h {
-            for x in 0..w {
-                state.map[y][x] = Cell::new(x, y, CellState::Dead, CellType::Prey);
+    fn cell(&self, universe: Option<&Universe>, x: usize, y: usize) -> Cell {
+        if let (Some(universe), Some(root)) = (universe, self.root) {
+            // The universe is centred on the middle of the screen
+            let wx = x as i64 - (self.w / 2) as i64;
+            let wy = y as i64 - (self.h / 2) as i64;
+            return if universe.get(root, wx, wy) {
+                Cell::prey(NEWBORN)
+            } else {
+                Cell::default()
+            };
+        }
+        if let Some(world) = &self.world {
+            return world.get(self.view.0 + x as i64, self.view.1 + y as i64);
+        }
+        match &self.bits {
+            Some(bits) if bits.get(x, y) => Cell::prey(NEWBORN),
+            Some(_) => Cell::default(),
+            None => self.map[(x, y)],
+        }
+    }
+
+    fn set(&mut self, x: usize, y: usize, cell: Cell) {
+        self.activity = None;
+        if let Some(world) = self.world.as_mut() {
+            world.set(self.view.0 + x as i64, self.view.1 + y as i64, cell);
+            return;
+        }
+        match self.bits.as_mut() {
+            Some(bits) => bits.set(x, y, cell.is_alive()),
+            None => self.map[(x, y)] = cell,
+        }
+    }
+
+    // Background under an empty cell at (x, y), terrain over resources
+    fn background(&self, terrain: &Terrain, x: usize, y: usize) -> Option<Color> {
+        terrain
+            .get(x, y)
+            .color()
+            .or_else(|| self.resources.as_ref().map(|r| r.color(x, y)))
+    }
+
+    fn count_population(&mut self) {
+        if self.root.is_some() {
+            // Counted by the universe
+            return;
+        }
+        if let Some(bits) = &self.bits {
+            self.set_single(bits.population());
+            return;
+        }
+        self.genes = GenePool::default();
+        self.epidemic = Epidemic::default();
+        if let Some(world) = &self.world {
+            self.population = world.population();
+            for cell in world.cells() {
+                self.genes.add(*cell);
+                self.epidemic.add(*cell);
             }
+            return;
+        }
+        self.population = [0; MAX_SPECIES];
+        for cell in self.map.iter().filter(|cell| cell.is_alive()) {
+            self.population[cell.kind().index()] += 1;
+            self.genes.add(*cell);
+            self.epidemic.add(*cell);
+        }
+    }
+
+    // Population of the single species engines
+    fn set_single(&mut self, population: usize) {
+        self.population = [0; MAX_SPECIES];
+        self.population[0] = population;
+    }
+
+    // Moves the viewport of an infinite world so `follow` is in the middle
+    fn follow(&mut self, follow: Follow) {
+        let Some(world) = &self.world else {
+            return;
+        };
+        let centre = match follow {
+            Follow::Centroid => world.centroid(),
+            Follow::Point(x, y) => Some((x, y)),
+        };
+        if let Some((x, y)) = centre {
+            self.view = (x - (self.w / 2) as i64, y - (self.h / 2) as i64);
         }
     }
 
     fn gen_clustered_random_map(state: &mut GameState, config: Config) {
-        let mut rng = rand::thread_rng();
+        let mut rng = generation_rng(config.seed, 0);
         let cluster_size = config.cluster_size as isize;
         let cluster_density = config.cluster_density;
-        let predetor_rate = config.predetor_rate;
+        let rules = config.web.rules(&config);
+        // Life-like rules only have a single species
+        let shares = if config.rule.is_some() {
+            vec![1.0]
+        } else {
+            config.web.shares(config.predetor_rate)
+        };
+        let total: f64 = shares.iter().sum();
 
         for _ in 0..config.population {
             let center_x = rng.gen_range(0..state.w);
@@ -75,12 +216,15 @@ impl GameState {
             for x in range_x.clone() {
                 for y in range_y.clone() {
                     if rng.gen_bool(cluster_density) {
-                        state.map[y][x].state = CellState::Alive(1);
-                        state.map[y][x].kind = if rng.gen_bool(predetor_rate) {
-                            CellType::Predetor
-                        } else {
-                            CellType::Prey
-                        };
+                        let kind = pick_species(&shares, total, &mut rng);
+                        let mut cell = newborn(kind, &rules);
+                        // The epidemic starts among a share of the first cells
+                        if config.infection > 0.0 && rng.gen_bool(config.infected) {
+                            cell = cell.with_health(Health::Infected);
+                        }
+                        if config.terrain.get(x, y) != Ground::Wall {
+                            state.set(x, y, cell);
+                        }
                     }
                 }
             }
@@ -88,24 +232,155 @@ impl GameState {
     }
 }
 
+// Everything needed to resume the run from a past generation
+#[derive(Debug, Clone)]
+struct Snapshot {
+    state: GameState,
+    config: Config,
+    applied: usize,
+}
+
 pub struct GameOfLife {
     pub config: Config,
     state: GameState,
+    // Preallocated buffer the next generation is written into
+    back: GameState,
+    // The config with the seasons of the step being simulated applied
+    season: Config,
+    // Parameters of each species, refreshed from `season` every step
+    rules: Vec<Rules>,
+    // Neighbour counts of the current state for square neighbourhoods
+    counts: SummedArea,
+    // What predation decided for each cell of the grid in the step being simulated
+    hunts: Grid<Hunt>,
+    // Node store in HashLife mode
+    universe: Option<Universe>,
+    export: Export,
+    recording: Option<CastRecording>,
+    log: Option<EventLogWriter>,
+    // Every event of the run in order, and how many of them have been applied
+    timeline: Vec<Entry>,
+    applied: usize,
+    history: History<Snapshot>,
+    paused: bool,
+    // Steps simulated in the last frame
+    frame_steps: usize,
+    metrics: Metrics,
+    metrics_log: Option<MetricsLog>,
 }
 
 impl GameOfLife {
     pub fn new(config: Config) -> Self {
-        Self {
+        let mut game = Self {
             config,
             state: GameState::init(config),
+            back: GameState::new(config),
+            season: config,
+            rules: config.web.rules(&config),
+            counts: SummedArea::default(),
+            hunts: Grid::default(),
+            universe: None,
+            export: Export::default(),
+            recording: None,
+            log: None,
+            timeline: Vec::new(),
+            applied: 0,
+            history: History::new(config.history.div_ceil(history::INTERVAL)),
+            paused: false,
+            frame_steps: 0,
+            metrics: Metrics::default(),
+            metrics_log: None,
+        };
+        if let (true, Some(rule)) = (config.hashlife, config.rule) {
+            // Seed the universe with a random map of the screen size
+            let seeded = GameState::init(Config {
+                hashlife: false,
+                ..config
+            });
+            let mut universe = Universe::new(rule);
+            let mut root = universe.empty_root();
+            for y in 0..config.h {
+                for x in 0..config.w {
+                    if seeded.cell(None, x, y).is_alive() {
+                        let wx = x as i64 - (config.w / 2) as i64;
+                        let wy = y as i64 - (config.h / 2) as i64;
+                        root = universe.set(root, wx, wy, true);
+                    }
+                }
+            }
+            game = game.with_universe(universe, root);
+        }
+        game.apply_scheduled(0..1);
+        game
+    }
+
+    // Starts a HashLife run from `root`, e.g. a loaded pattern
+    pub fn with_universe(mut self, universe: Universe, root: NodeId) -> Self {
+        self.config.rule = Some(universe.rule());
+        self.state = GameState::new(self.config);
+        self.state.root = Some(root);
+        self.state.set_single(universe.population(root) as usize);
+        self.universe = Some(universe);
+        self
+    }
+
+    // Re-simulates a logged run, `speed` scales the recorded fps
+    pub fn replay(log: EventLog, speed: f64) -> Self {
+        let mut config = log.config;
+        if config.fps > 0 {
+            config.fps = ((config.fps as f64 * speed).round() as usize).max(1);
         }
+        let mut game = GameOfLife::new(config);
+        game.timeline = log.entries;
+        game.apply_pending();
+        game
+    }
+
+    pub fn with_export(mut self, export: Export) -> Self {
+        self.export = export;
+        self
+    }
+
+    pub fn with_recording(mut self, recording: CastRecording) -> Self {
+        self.recording = Some(recording);
+        self
+    }
+
+    pub fn with_metrics(mut self, log: MetricsLog) -> Self {
+        self.metrics_log = Some(log);
+        self
+    }
+
+    pub fn with_log(mut self, log: EventLogWriter) -> Self {
+        self.log = Some(log);
+        self
     }
 
     fn info(&self) -> String {
         let mut info = String::new();
         info.push_str(&format!("generation: {}\n", self.state.generation));
-        info.push_str(&format!("Preys: {}\n", self.state.preys));
-        info.push_str(&format!("predators: {}\n", self.state.predators));
+        if self.paused {
+            info.push_str("paused\n");
+        }
+        info.push_str(&format!("history: {}\n", self.history.len()));
+        let skip = match self.config.frame_skip {
+            FrameSkip::Fixed(_) => "fixed",
+            FrameSkip::Auto => "auto",
+        };
+        info.push_str(&format!("steps/frame: {} ({})\n", self.frame_steps, skip));
+        let web = self.config.web;
+        for kind in web.kinds() {
+            let population = self.state.population[kind.index()];
+            info.push_str(&format!("{}: {}", web.name(kind), population));
+            if self.config.infection > 0.0 {
+                let outbreak = self.state.epidemic.get(kind);
+                info.push_str(&format!(
+                    " (infected {}, recovered {})",
+                    outbreak.infected, outbreak.recovered
+                ));
+            }
+            info.push('\n');
+        }
 
         info.push_str(&format!("Width: {}\n", self.config.w));
         info.push_str(&format!("Height: {}\n", self.config.h));
@@ -127,12 +402,63 @@ impl GameOfLife {
         info.push_str(&format!("resistence: {}\n", self.config.resistence));
         info.push_str(&format!("aging_rate: {}\n", self.config.aging_rate));
         info.push_str(&format!("predetor_rate: {}\n", self.config.predetor_rate));
+        for season in &self.config.schedule.seasons {
+            let param = season.amplitude.current(&self.season);
+            info.push_str(&format!("season {}: {}\n", param.label(), param.value()));
+        }
+        if let Some(rule) = self.config.rule {
+            let engine = if self.universe.is_some() {
+                "hashlife"
+            } else if self.state.bits.is_some() {
+                "bit-packed"
+            } else {
+                "per-cell"
+            };
+            info.push_str(&format!("rule: {} ({})\n", rule, engine));
+        }
+        if let Some(universe) = &self.universe {
+            info.push_str(&format!(
+                "step: 2^{} generations, nodes: {}\n",
+                self.config.step_exp,
+                universe.nodes()
+            ));
+        }
+        if let Some(activity) = &self.state.activity {
+            let (active, tiles) = activity.count();
+            info.push_str(&format!("active tiles: {} / {}\n", active, tiles));
+        }
+        if let Some(world) = &self.state.world {
+            info.push_str(&format!(
+                "tiles: {}, view: {}, {}\n",
+                world.tiles(),
+                self.state.view.0,
+                self.state.view.1
+            ));
+        }
+        info.push_str(&format!("seed: {}\n", self.config.seed));
+        info.push_str(&format!("threads: {}\n", self.config.threads));
+        if let Some(resources) = &self.state.resources {
+            info.push_str(&format!("resources: {:.2}\n", resources.mean()));
+        }
+        let names: Vec<_> = web.kinds().map(|kind| web.name(kind)).collect();
+        let names = names.join(" / ");
+        info.push_str(&format!("\ntransitions ({})\n", names));
+        info.push_str(
+            &self
+                .state
+                .census
+                .summary(web.len(), self.config.infection > 0.0),
+        );
+        if self.config.rule.is_none() {
+            info.push_str(&format!("\nmean traits ({})\n", names));
+            info.push_str(&self.state.genes.summary(web.len()));
+        }
         info
     }
 
     pub fn run(&mut self) -> Result<()> {
+        let _terminal = RawTerminal::enable()?;
         let mut stdout = io::stdout();
-        let fps = self.config.fps;
 
         let clear = |stdout: &mut Stdout| -> Result<()> {
             stdout.queue(cursor::MoveTo(0, 0))?;
@@ -140,240 +466,2027 @@ impl GameOfLife {
             Ok(())
         };
 
-        let hold_fps = |elapsed: Duration| {
-            let frame_duration = Duration::from_secs_f64(1.0 / fps as f64);
-            if elapsed < frame_duration {
-                sleep(frame_duration - elapsed);
+        let hold_fps = |elapsed: Duration, fps: usize| {
+            if let Some(frame_duration) = frame_duration(fps) {
+                if elapsed < frame_duration {
+                    sleep(frame_duration - elapsed);
+                }
             }
         };
 
-        let print_info = |stdout: &mut Stdout, frame_start: Instant, info: String| -> Result<()> {
-            let actual_fps = 1.0 / frame_start.elapsed().as_secs_f64();
-
-            stdout
+        let print_info = |frame: &mut Vec<u8>, rates: Rates, info: String| -> Result<()> {
+            frame
                 .queue(cursor::MoveTo(0, 0))?
+                .queue(style::PrintStyledContent(rates.summary().blue()))?
                 .queue(style::PrintStyledContent(
-                    format!("FPS: {actual_fps}").blue(),
-                ))?
-                .queue(style::PrintStyledContent(format!("\n{}", info).blue()))?;
+                    format!("\n{}", info).replace('\n', "\r\n").blue(),
+                ))?;
             Ok(())
         };
 
-        stdout.queue(cursor::Hide)?;
         clear(&mut stdout)?;
 
+        // Everything drawn in a frame is buffered so it can be recorded as well
+        let mut frame = Vec::new();
+        // Time the last frame took to write out, kept free in auto frame skip
+        let mut output = Duration::ZERO;
         loop {
             let frame_start = Instant::now();
-            self.simulate(&mut stdout)?;
-            let frame_end = frame_start.elapsed();
+            self.metrics.start_frame();
+
+            let mut advance = !self.paused;
+            for action in controls::poll(&self.config)? {
+                match action {
+                    Action::Quit => return Ok(()),
+                    Action::TogglePause => self.paused = !self.paused,
+                    Action::StepForward => advance = true,
+                    Action::StepBack if self.paused => self.step_back(),
+                    Action::StepBack => {}
+                    Action::Apply(event) => self.record_event(event)?,
+                }
+            }
 
+            let render_start = Instant::now();
+            self.draw(&mut frame)?;
+            if advance {
+                self.capture()?;
+            }
+            let mut render = render_start.elapsed();
+            if advance {
+                let sim_start = Instant::now();
+                let generation = self.state.generation;
+                if self.paused {
+                    self.step();
+                    self.frame_steps = 1;
+                } else {
+                    self.step_frame(frame_start, output, usize::MAX);
+                }
+                let generations = self.state.generation.saturating_sub(generation);
+                self.metrics.record_sim(sim_start.elapsed(), generations);
+            }
+
+            let output_start = Instant::now();
             if self.config.info {
-                print_info(&mut stdout, frame_start, self.info())?;
+                print_info(&mut frame, self.metrics.rates(), self.info())?;
+            }
+            stdout.write_all(&frame)?;
+            if let Some(recording) = self.recording.as_mut() {
+                recording.record(&frame)?;
             }
+            frame.clear();
+            output = output_start.elapsed();
+            render += output;
+            self.metrics.record_render(render);
+            self.log_metrics()?;
 
-            hold_fps(frame_end);
+            hold_fps(frame_start.elapsed(), self.config.fps);
             clear(&mut stdout)?;
         }
     }
 
-    fn simulate(&mut self, stdout: &mut Stdout) -> Result<()> {
-        let mut next = GameState::new(self.config);
-        next.generation = self.state.generation + 1;
-        self.draw(&mut next, stdout)?;
-        // self.state = next;
-        std::mem::swap(&mut self.state, &mut next);
+    // Runs `generations` generations without a terminal, exporting frames and
+    // metrics, then prints where the run ended up as JSON
+    pub fn run_headless(&mut self, generations: usize) -> Result<()> {
+        let target = self.state.generation + generations;
+        while self.state.generation < target {
+            let frame_start = Instant::now();
+            self.metrics.start_frame();
+
+            let render_start = Instant::now();
+            self.capture()?;
+            self.metrics.record_render(render_start.elapsed());
+
+            let sim_start = Instant::now();
+            let generation = self.state.generation;
+            let steps = (target - generation).div_ceil(self.generations_per_step());
+            self.step_frame(frame_start, Duration::ZERO, steps);
+            self.metrics
+                .record_sim(sim_start.elapsed(), self.state.generation - generation);
+            self.log_metrics()?;
+        }
+        println!("{}", serde_json::to_string(&self.sample())?);
         Ok(())
     }
 
-    fn draw(&self, next: &mut GameState, stdout: &mut Stdout) -> Result<()> {
-        let mut buf = String::new();
-        for row in self.state.map.iter() {
-            for cell in row.iter() {
-                self.apply_rules(cell, next);
-                let symbol = match cell.state {
-                    CellState::Alive(age) => {
-                        let live = (age as f64 / self.config.age as f64) * 100.0;
-                        let color = if live >= 75.0 {
-                            match cell.kind {
-                                // Red for Prey
-                                CellType::Prey => Color::AnsiValue(196),
-                                // Blue for Predator
-                                CellType::Predetor => Color::AnsiValue(21),
-                            }
-                        } else if live >= 50.0 {
-                            match cell.kind {
-                                //  Orange for Prey
-                                CellType::Prey => Color::AnsiValue(214),
-                                // Magenta for Predator
-                                CellType::Predetor => Color::AnsiValue(201),
-                            }
-                        } else if live >= 25.0 {
-                            match cell.kind {
-                                // Yellow for Prey
-                                CellType::Prey => Color::AnsiValue(226),
-                                // Violet for Predator
-                                CellType::Predetor => Color::AnsiValue(135),
-                            }
-                        } else {
-                            match cell.kind {
-                                // Green for Prey
-                                CellType::Prey => Color::AnsiValue(47),
-                                // Cyan for Predator
-                                CellType::Predetor => Color::AnsiValue(81),
-                            }
-                        };
-
-                        match cell.kind {
-                            CellType::Prey => next.preys += 1,
-                            CellType::Predetor => next.predators += 1,
-                        };
-                        style::style(format!("{}", cell)).with(color)
-                    }
-                    CellState::Dead => {
-                        match cell.kind {
-                            CellType::Prey => next.preys.saturating_sub(1),
-                            CellType::Predetor => next.predators.saturating_sub(1),
-                        };
-                        // Dark grey
-                        style::style(format!("{}", cell)).black()
+    fn capture(&mut self) -> Result<()> {
+        let (state, universe) = (&self.state, self.universe.as_ref());
+        let (web, age) = (self.config.web, self.config.age);
+        let terrain = self.config.terrain;
+        self.export.capture(state.w, state.h, |x, y| {
+            let cell = state.cell(universe, x, y);
+            match state.background(terrain, x, y) {
+                Some(background) if !cell.is_alive() => background,
+                _ => web.color(cell, age),
+            }
+        })
+    }
+
+    fn sample(&self) -> Sample {
+        let web = self.config.web;
+        let species = web
+            .kinds()
+            .map(|kind| SpeciesSample {
+                name: web.name(kind).to_string(),
+                population: self.state.population[kind.index()],
+                traits: self.state.genes.mean(kind),
+                census: self.state.census.counts(kind, self.config.infection > 0.0),
+                outbreak: (self.config.infection > 0.0).then(|| self.state.epidemic.get(kind)),
+            })
+            .collect();
+        Sample {
+            generation: self.state.generation,
+            species,
+            resources: self.state.resources.as_ref().map(Resources::mean),
+            rates: self.metrics.rates(),
+        }
+    }
+
+    fn log_metrics(&mut self) -> Result<()> {
+        let sample = self.sample();
+        if let Some(log) = self.metrics_log.as_mut() {
+            log.write(&sample)?;
+        }
+        Ok(())
+    }
+
+    // Simulates the steps of one frame, at most `max_steps` of them. Auto frame
+    // skip keeps stepping until the time left in the frame is what writing the
+    // last one took.
+    fn step_frame(&mut self, frame_start: Instant, output: Duration, max_steps: usize) {
+        self.frame_steps = 0;
+        match self.config.frame_skip {
+            FrameSkip::Fixed(steps) => {
+                let steps = steps.min(max_steps);
+                for _ in 0..steps {
+                    self.step();
+                }
+                self.frame_steps = steps;
+            }
+            FrameSkip::Auto => {
+                let budget = frame_duration(self.config.fps)
+                    .unwrap_or(AUTO_FRAME)
+                    .saturating_sub(output);
+                loop {
+                    self.step();
+                    self.frame_steps += 1;
+                    if frame_start.elapsed() >= budget || self.frame_steps >= max_steps {
+                        break;
                     }
+                }
+            }
+        }
+    }
+
+    // Applies an interactive event now and appends it to the timeline. Acting
+    // on an earlier generation branches off, dropping the events and history
+    // that followed it.
+    fn record_event(&mut self, event: Event) -> Result<()> {
+        let entry = Entry {
+            generation: self.state.generation,
+            event,
+        };
+        let branched = self.timeline.len() > self.applied;
+        self.history.branch();
+        self.timeline.truncate(self.applied);
+        self.timeline.push(entry);
+        if let Some(log) = self.log.as_mut() {
+            if branched {
+                log.rewrite(&self.timeline)?;
+            } else {
+                log.write(entry)?;
+            }
+        }
+        self.apply_pending();
+        Ok(())
+    }
+
+    fn apply_pending(&mut self) {
+        while let Some(entry) = self.timeline.get(self.applied) {
+            if entry.generation > self.state.generation {
+                break;
+            }
+            let event = entry.event;
+            self.applied += 1;
+            self.apply(event);
+        }
+    }
+
+    fn apply(&mut self, event: Event) {
+        match event {
+            Event::Set(param) => self.config.set(param),
+            Event::Meteor { x, y, radius } => self.strike(x, y, radius),
+            Event::Spawn { x, y, kind } => {
+                let kind = if self.config.rule.is_some() || kind.index() >= self.config.web.len() {
+                    CellType::PREY
+                } else {
+                    kind
                 };
+                if x >= self.state.w || y >= self.state.h {
+                    return;
+                }
+                if self.config.terrain.get(x, y) == Ground::Wall {
+                    return;
+                }
+                if let (Some(universe), Some(root)) = (self.universe.as_mut(), self.state.root) {
+                    let wx = x as i64 - (self.state.w / 2) as i64;
+                    let wy = y as i64 - (self.state.h / 2) as i64;
+                    let root = universe.set(root, wx, wy, true);
+                    self.state.root = Some(root);
+                    self.state.set_single(universe.population(root) as usize);
+                } else {
+                    let rules = self.config.web.rules(&self.config);
+                    self.state.set(x, y, newborn(kind, &rules));
+                    self.state.count_population();
+                }
+            }
+        }
+    }
+
+    // Applies the events the schedule has for any of `generations`
+    fn apply_scheduled(&mut self, generations: std::ops::Range<usize>) {
+        for event in self.config.schedule.events(generations) {
+            self.apply(event);
+        }
+    }
+
+    // Kills every cell within `radius` of (x, y) on the screen
+    fn strike(&mut self, x: usize, y: usize, radius: usize) {
+        let r = radius as i64;
+        for dy in -r..=r {
+            for dx in -r..=r {
+                if dx * dx + dy * dy > r * r {
+                    continue;
+                }
+                let (cx, cy) = (x as i64 + dx, y as i64 + dy);
+                if let (Some(universe), Some(root)) = (self.universe.as_mut(), self.state.root) {
+                    let wx = cx - (self.state.w / 2) as i64;
+                    let wy = cy - (self.state.h / 2) as i64;
+                    self.state.root = Some(universe.set(root, wx, wy, false));
+                } else if let Some(world) = self.state.world.as_mut() {
+                    world.set(
+                        self.state.view.0 + cx,
+                        self.state.view.1 + cy,
+                        Cell::default(),
+                    );
+                } else if (0..self.state.w as i64).contains(&cx)
+                    && (0..self.state.h as i64).contains(&cy)
+                {
+                    self.state.set(cx as usize, cy as usize, Cell::default());
+                }
+            }
+        }
+        self.state.activity = None;
+        if let (Some(universe), Some(root)) = (&self.universe, self.state.root) {
+            self.state.set_single(universe.population(root) as usize);
+        } else {
+            self.state.count_population();
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            state: self.state.clone(),
+            config: self.config,
+            applied: self.applied,
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.state = snapshot.state;
+        self.config = snapshot.config;
+        self.applied = snapshot.applied;
+    }
+
+    // Steps back through the history. Runs are deterministic, so the run is
+    // simulated again from the nearest snapshot. Past the oldest one there is
+    // nothing to go back to.
+    fn step_back(&mut self) {
+        if self.state.generation == 0 {
+            return;
+        }
+        let target = self.state.generation - 1;
+        let current = self.snapshot();
+        let newer = |snapshot: &Snapshot| snapshot.state.generation > target;
+        let Some(previous) = self.history.undo(current, newer) else {
+            return;
+        };
+        self.restore(previous);
+        while self.state.generation + self.generations_per_step() <= target {
+            self.checkpoint();
+            self.advance();
+        }
+    }
+
+    pub fn generation(&self) -> usize {
+        self.state.generation
+    }
+
+    pub fn step(&mut self) {
+        if self.history.is_rewound() {
+            self.checkpoint();
+            if let Some(next) = self.history.redo() {
+                self.restore(next);
+            }
+            return;
+        }
+        self.checkpoint();
+        self.advance();
+    }
+
+    // Remembers the current state every `history::INTERVAL` steps, unless it
+    // already is the latest snapshot
+    fn checkpoint(&mut self) {
+        let generation = self.state.generation;
+        let steps = generation / self.generations_per_step();
+        let recorded = self
+            .history
+            .latest()
+            .is_some_and(|snapshot| snapshot.state.generation == generation);
+        if self.config.history > 0 && steps.is_multiple_of(history::INTERVAL) && !recorded {
+            self.history.record(self.snapshot());
+        }
+    }
+
+    // Simulates the next step
+    fn advance(&mut self) {
+        // Write into the spare buffer, then swap it in as the current state
+        let mut next = std::mem::take(&mut self.back);
+        if next.w != self.state.w || next.h != self.state.h {
+            next = GameState::new(self.config);
+        }
+        next.generation = self.state.generation + self.generations_per_step();
+        self.season = self.config.schedule.season(&self.config, next.generation);
+        self.rules = self.config.web.rules(&self.season);
+
+        if let (Some(universe), Some(root)) = (self.universe.as_mut(), self.state.root) {
+            let root = universe.advance(root, self.config.step_exp);
+            next.root = Some(root);
+            next.set_single(universe.population(root) as usize);
+            next.census = Census::default();
+        } else if let (Some(bits), Some(rule)) = (&self.state.bits, self.config.rule) {
+            let next_bits = next
+                .bits
+                .get_or_insert_with(|| BitGrid::new(self.state.w, self.state.h));
+            next.census = bits.step(rule, next_bits);
+        } else if let Some(world) = &self.state.world {
+            let (mut world, census) = self.step_world(world, next.generation);
+            self.move_world(&mut world, next.generation);
+            next.world = Some(world);
+            next.census = census;
+            next.view = self.state.view;
+        } else {
+            if self.config.shape == Shape::Moore {
+                self.counts
+                    .build(&self.state.map, &self.rules, self.config.infection > 0.0);
+            }
+            let generation = next.generation;
+            self.hunt_grid(generation);
+            next.census = self.step_rows(&mut next.map, generation);
+            self.move_grid(&mut next.map, generation);
+            self.grow_resources(&next.map, &mut next.resources);
+            // B/S rules are deterministic, so only a change can wake a tile up.
+            // The predator-prey rules are random wherever there is life.
+            next.activity = Some(Activity::track(
+                &self.state.map,
+                &next.map,
+                self.config.radius,
+                self.config.rule.is_some(),
+            ));
+        }
+        next.count_population();
+        next.follow(self.config.follow);
+        std::mem::swap(&mut self.state, &mut next);
+        // HashLife steps skip generations, their scheduled events still apply
+        let stepped = next.generation + 1..self.state.generation + 1;
+        self.back = next;
+        self.apply_scheduled(stepped);
+        self.apply_pending();
+    }
+
+    fn generations_per_step(&self) -> usize {
+        if self.universe.is_some() {
+            1 << self.config.step_exp
+        } else {
+            1
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    fn step_rows(&self, map: &mut Grid<Cell>, generation: usize) -> Census {
+        use rayon::prelude::*;
+
+        if self.config.threads == 1 {
+            return self.step_rows_serial(map, generation);
+        }
+        map.par_rows_mut()
+            .enumerate()
+            .map(|(y, row)| self.step_row(y, row, generation))
+            .reduce(Census::default, |mut census, row| {
+                census.merge(&row);
+                census
+            })
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn step_rows(&self, map: &mut Grid<Cell>, generation: usize) -> Census {
+        self.step_rows_serial(map, generation)
+    }
+
+    fn step_rows_serial(&self, map: &mut Grid<Cell>, generation: usize) -> Census {
+        let mut census = Census::default();
+        for (y, row) in map.rows_mut().enumerate() {
+            census.merge(&self.step_row(y, row, generation));
+        }
+        census
+    }
+
+    // Computes one row of the next generation. Every row has its own random
+    // stream, so the outcome doesn't depend on how rows are split across threads.
+    fn step_row(&self, y: usize, row: &mut [Cell], generation: usize) -> Census {
+        let mut rng = row_rng(self.config.seed, generation, y);
+        let mut census = Census::default();
+        let mut x = 0;
+        while x < row.len() {
+            if self.is_quiet(x, y) {
+                let end = ((x / activity::SIZE + 1) * activity::SIZE).min(row.len());
+                self.step_quiet(x, y, &mut row[x..end], &mut rng, &mut census);
+                x = end;
+                continue;
+            }
+            let cell = self.state.map[(x, y)];
+            let neighbours = self.count_neighbours(&self.state.map, &self.counts, x, y);
+            let hunt = self.hunts.get(x, y).copied().unwrap_or_default();
+            let site = Site {
+                ground: self.config.terrain.get(x, y),
+                resource: self.state.resources.as_ref().map(|r| r.level(x, y)),
+            };
+            let inherit = |kind, rng: &mut StdRng| self.inherit(&self.state.map, x, y, kind, rng);
+            row[x] = self.step_cell(
+                cell,
+                neighbours,
+                hunt,
+                site,
+                inherit,
+                self.season.mutation,
+                &mut rng,
+                &mut census,
+            );
+            x += 1;
+        }
+        census
+    }
+
+    fn is_quiet(&self, x: usize, y: usize) -> bool {
+        // With a reproduction threshold of 0 even empty areas breed, and
+        // grazing changes the resources under any live prey
+        let quiescent = self.config.rule.is_some()
+            || self.state.resources.is_none()
+                && self
+                    .rules
+                    .iter()
+                    .all(|rules| rules.is_predator() || rules.reproduction > 0);
+        quiescent
+            && self
+                .state
+                .activity
+                .as_ref()
+                .is_some_and(|activity| !activity.is_active(x, y))
+    }
+
+    // Steps a run of cells from a quiet tile starting at (x, y). They keep
+    // their state, except that empty cells under the food web rules can
+    // mutate into the first species. Rather than rolling for every cell, the
+    // gaps between mutations are drawn from a geometric distribution, which
+    // gives the same odds.
+    fn step_quiet(
+        &self,
+        x: usize,
+        y: usize,
+        row: &mut [Cell],
+        rng: &mut StdRng,
+        census: &mut Census,
+    ) {
+        for (i, next_cell) in row.iter_mut().enumerate() {
+            *next_cell = self.state.map[(x + i, y)];
+            if next_cell.is_alive() {
+                census.record(next_cell.kind(), Transition::Survived);
+            }
+        }
+        if self.config.rule.is_some() {
+            return;
+        }
+        let mut i = mutation_gap(self.season.mutation, rng);
+        while i < row.len() {
+            if self.config.terrain.get(x + i, y).allows_births() {
+                row[i] = newborn(CellType::PREY, &self.rules);
+                census.record(CellType::PREY, Transition::Mutated);
+            }
+            i = i
+                .saturating_add(1)
+                .saturating_add(mutation_gap(self.season.mutation, rng));
+        }
+    }
+
+    // Steps every tile with life in or near it, tiles further away stay empty
+    fn step_world(&self, world: &World, generation: usize) -> (World, Census) {
+        let reach = self.config.radius.div_ceil(TILE) as i64;
+        let keys = world.active(reach);
+        let hunts = self.hunt_world(world, generation);
+        let tiles = self.step_tiles(world, &keys, &hunts, generation);
+
+        let mut next = World::default();
+        let mut census = Census::default();
+        for (key, (tile, tile_census)) in keys.into_iter().zip(tiles) {
+            census.merge(&tile_census);
+            next.insert(key, tile);
+        }
+        (next, census)
+    }
+
+    #[cfg(feature = "parallel")]
+    fn step_tiles(
+        &self,
+        world: &World,
+        keys: &[TileKey],
+        hunts: &HashMap<TileKey, Grid<Hunt>>,
+        generation: usize,
+    ) -> Vec<(Grid<Cell>, Census)> {
+        use rayon::prelude::*;
+
+        if self.config.threads == 1 {
+            return self.step_tiles_serial(world, keys, hunts, generation);
+        }
+        keys.par_iter()
+            .map(|&key| self.step_tile(world, key, hunts.get(&key), generation))
+            .collect()
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn step_tiles(
+        &self,
+        world: &World,
+        keys: &[TileKey],
+        hunts: &HashMap<TileKey, Grid<Hunt>>,
+        generation: usize,
+    ) -> Vec<(Grid<Cell>, Census)> {
+        self.step_tiles_serial(world, keys, hunts, generation)
+    }
+
+    fn step_tiles_serial(
+        &self,
+        world: &World,
+        keys: &[TileKey],
+        hunts: &HashMap<TileKey, Grid<Hunt>>,
+        generation: usize,
+    ) -> Vec<(Grid<Cell>, Census)> {
+        keys.iter()
+            .map(|&key| self.step_tile(world, key, hunts.get(&key), generation))
+            .collect()
+    }
+
+    // Computes one tile of the next generation from a copy of it padded with
+    // its surroundings out to the neighbourhood radius
+    fn step_tile(
+        &self,
+        world: &World,
+        key: TileKey,
+        hunts: Option<&Grid<Hunt>>,
+        generation: usize,
+    ) -> (Grid<Cell>, Census) {
+        let radius = self.config.radius;
+        let mut local = Grid::new(TILE + 2 * radius, TILE + 2 * radius, Cell::default());
+        world.copy_region(
+            key.0 * TILE as i64 - radius as i64,
+            key.1 * TILE as i64 - radius as i64,
+            &mut local,
+        );
+        let mut counts = SummedArea::default();
+        if self.config.shape == Shape::Moore {
+            counts.build(&local, &self.rules, self.config.infection > 0.0);
+        }
+
+        // Only tiles that already hold life mutate, otherwise mutations in the
+        // empty tiles around them would grow the world by a ring every generation
+        let mutation = if world.contains(key) {
+            self.season.mutation
+        } else {
+            0.0
+        };
+        let mut rng = tile_rng(self.config.seed, generation, key);
+        let mut census = Census::default();
+        let mut tile = Grid::new(TILE, TILE, Cell::default());
+        for (y, row) in tile.rows_mut().enumerate() {
+            for (x, next_cell) in row.iter_mut().enumerate() {
+                let (lx, ly) = (x + radius, y + radius);
+                let neighbours = self.count_neighbours(&local, &counts, lx, ly);
+                let hunt = hunts.map_or(Hunt::None, |hunts| hunts[(x, y)]);
+                let inherit = |kind, rng: &mut StdRng| self.inherit(&local, lx, ly, kind, rng);
+                *next_cell = self.step_cell(
+                    local[(lx, ly)],
+                    neighbours,
+                    hunt,
+                    Site::default(),
+                    inherit,
+                    mutation,
+                    &mut rng,
+                    &mut census,
+                );
+            }
+        }
+        (tile, census)
+    }
+
+    // Next state of `cell`, tallying what happened to it
+    #[allow(clippy::too_many_arguments)]
+    fn step_cell(
+        &self,
+        cell: Cell,
+        neighbours: Neighbours,
+        hunt: Hunt,
+        site: Site,
+        inherit: impl FnOnce(CellType, &mut StdRng) -> Genome,
+        mutation: f64,
+        rng: &mut StdRng,
+        census: &mut Census,
+    ) -> Cell {
+        let outcome = self.apply_rules(cell, neighbours, hunt, site, inherit, mutation, rng);
+        let (next_cell, transition) = self.infect(outcome, neighbours, rng);
+        if let Some(transition) = transition {
+            // Deaths are attributed to the species that died, births to the newborn
+            let kind = match transition {
+                Transition::Born | Transition::Mutated => next_cell.kind(),
+                _ => cell.kind(),
+            };
+            census.record(kind, transition);
+        }
+        // The prey was eaten and the predator's newborn took its place
+        if let (Hunt::Offspring(kind, _), true) = (hunt, next_cell.is_alive()) {
+            census.record(kind, Transition::Born);
+        }
+        next_cell
+    }
+
+    fn draw(&self, out: &mut impl Write) -> Result<()> {
+        let mut buf = String::new();
+        for y in 0..self.state.h {
+            for x in 0..self.state.w {
+                let cell = self.state.cell(self.universe.as_ref(), x, y);
+                let web = self.config.web;
+                let mut symbol =
+                    style::style(web.glyph(cell)).with(web.color(cell, self.config.age));
+                let background = self.state.background(self.config.terrain, x, y);
+                if let (false, Some(background)) = (cell.is_alive(), background) {
+                    symbol = symbol.on(background);
+                }
                 buf.push_str(&symbol.to_string());
             }
-            buf.push_str("\n");
+            // Raw mode doesn't return the carriage on a line feed
+            buf.push_str("\r\n");
         }
-        stdout.queue(cursor::MoveTo(0, 0))?.queue(Print(buf))?;
+        out.queue(cursor::MoveTo(0, 0))?.queue(Print(buf))?;
         Ok(())
     }
 
-    fn apply_rules(&self, cell: &Cell, next: &mut GameState) {
-        let reprod = self.config.reproduction;
-        let over = self.config.overpopulation;
-        let under = self.config.underpopulation;
-        let survival = self.config.survivability;
-        let max_age = self.config.age;
-        let resistence = self.config.resistence;
-        let aging = self.config.aging_rate;
-        let mutation = thread_rng().gen_bool(self.config.mutation);
-        let (prey, predators) = self.count_neighbours(cell.x, cell.y);
-
-        next.map[cell.y][cell.x] = match cell.state {
-            CellState::Alive(age) => {
-                match cell.kind {
-                    CellType::Prey => {
-                        // Age - prey dies of old age
-                        if age >= max_age {
-                            Cell::new(cell.x, cell.y, CellState::Dead, cell.kind)
-                        }
-                        // Eaten by predator - prey dies if predators nearby exceed resistance threshold
-                        else if predators >= resistence && !thread_rng().gen_bool(0.01) {
-                            Cell::new(cell.x, cell.y, CellState::Dead, cell.kind)
-                        }
-                        // Underpopulation - prey dies if not enough prey around to survive
-                        else if prey < under {
-                            Cell::new(cell.x, cell.y, CellState::Dead, cell.kind)
-                        }
-                        // Overpopulation - prey dies due to overcrowding
-                        else if prey > over {
-                            Cell::new(cell.x, cell.y, CellState::Dead, cell.kind)
-                        }
-                        // Prey reproduction - breed new prey based on reproduction conditions
-                        else if prey >= reprod && prey <= over && thread_rng().gen_bool(0.1) {
-                            Cell::prey(cell.x, cell.y, CellState::Alive(1)) // Newborn prey
-                        }
-                        // Stay Alive - prey survives under normal conditions
-                        else if prey >= survival && prey <= over {
-                            Cell::prey(cell.x, cell.y, CellState::Alive(age + 1))
-                        } else {
-                            Cell::new(cell.x, cell.y, cell.state, cell.kind)
-                        }
+    #[allow(clippy::too_many_arguments)]
+    fn apply_rules(
+        &self,
+        cell: Cell,
+        neighbours: Neighbours,
+        hunt: Hunt,
+        site: Site,
+        // Genome of a newborn of the given species in this cell
+        inherit: impl FnOnce(CellType, &mut StdRng) -> Genome,
+        mutation: f64,
+        rng: &mut StdRng,
+    ) -> (Cell, Option<Transition>) {
+        let gene_mutation = self.config.gene_mutation;
+        // Prey need enough resources under them to survive or be born
+        let grazed = site
+            .resource
+            .is_none_or(|level| level >= self.config.grazing);
+        let births = site.ground.allows_births();
+        // Prey breed more often on fertile ground
+        let fertility = if site.ground == Ground::Fertile {
+            self.config.fertility
+        } else {
+            1.0
+        };
+
+        // Nothing lives in a wall
+        if site.ground == Ground::Wall {
+            return (Cell::new(CellState::Dead, cell.kind()), None);
+        }
+
+        // Life-like rules on neighbourhoods the bit engine can't handle
+        if let Some(rule) = self.config.rule {
+            let neighbours = neighbours.total();
+            return match cell.state() {
+                CellState::Alive { .. } if rule.survives(neighbours) => {
+                    (cell, Some(Transition::Survived))
+                }
+                CellState::Alive { .. } if neighbours < rule.min_survival() => (
+                    Cell::new(CellState::Dead, cell.kind()),
+                    Some(Transition::Isolated),
+                ),
+                CellState::Alive { .. } => (
+                    Cell::new(CellState::Dead, cell.kind()),
+                    Some(Transition::Overcrowded),
+                ),
+                CellState::Dead if rule.births(neighbours) => {
+                    (Cell::prey(NEWBORN), Some(Transition::Born))
+                }
+                CellState::Dead => (cell, None),
+            };
+        }
+
+        let mutation = rng.gen_bool(mutation);
+
+        match cell.state() {
+            CellState::Alive {
+                age,
+                energy,
+                health,
+            } => {
+                let kind = cell.kind();
+                let rules = &self.rules[kind.index()];
+                let reprod = rules.reproduction;
+                let over = rules.overpopulation;
+                let under = rules.underpopulation;
+                let survival = rules.survivability;
+                // Cells of the same species and cells it feeds on around it
+                let own = neighbours.of(kind);
+                let food = neighbours.sum(rules.food);
+                // A live cell's own traits stand in for the species' rules
+                let genome = cell.genome();
+                let max_age = genome.max_age;
+                let aging = genome.aging_rate;
+
+                // Eaten by predator - a predator caught it in the hunt before
+                // this step, see `predation::hunt`
+                if let (Hunt::Offspring(parent_kind, parent), true) = (hunt, births) {
+                    let genome = parent.mutate(gene_mutation, rng);
+                    (
+                        child(parent_kind, genome, &self.rules),
+                        Some(Transition::Eaten),
+                    )
+                } else if matches!(hunt, Hunt::Eaten | Hunt::Offspring(..)) {
+                    (Cell::new(CellState::Dead, kind), Some(Transition::Eaten))
+                } else if !rules.is_predator() {
+                    // Prey starves once the resources under it run out
+                    if !grazed {
+                        (Cell::new(CellState::Dead, kind), Some(Transition::Starved))
                     }
-                    CellType::Predetor => {
-                        // Age - predator dies of old age
-                        if age >= max_age {
-                            Cell::new(cell.x, cell.y, CellState::Dead, cell.kind)
-                        }
-                        // Predator dies due to underpopulation - not enough other predators around
-                        else if predators < under {
-                            Cell::new(cell.x, cell.y, CellState::Dead, cell.kind)
-                        }
-                        // Predator dies due to overpopulation - too many predators in one area
-                        else if predators > over {
-                            Cell::new(cell.x, cell.y, CellState::Dead, cell.kind)
-                        }
-                        // Predator dies of hunger if it hasn't found food for too long
-                        else if prey == 0 && thread_rng().gen_bool(0.5) {
-                            Cell::new(cell.x, cell.y, CellState::Dead, cell.kind)
-                        // Starve to death
-                        }
-                        // Predator survives if it finds prey and isn't overcrowded
-                        else if prey > 0 && predators <= over {
-                            Cell::prededator(cell.x, cell.y, CellState::Alive(age + 1))
-                        // Reset hunger after eating
-                        }
-                        // No food, predator ages faster and gets hungrier
-                        else if prey == 0 {
-                            Cell::prededator(cell.x, cell.y, CellState::Alive(age + aging))
-                        }
-                        // Predator reproduction - breed new predator based on reproduction conditions
-                        else if prey > 0 && predators <= over && thread_rng().gen_bool(0.1) {
-                            Cell::prededator(cell.x, cell.y, CellState::Alive(1))
-                            // Newborn predator
-                        } else {
-                            Cell::new(cell.x, cell.y, cell.state, cell.kind)
-                        }
+                    // Age - prey dies of old age
+                    else if age >= max_age {
+                        (Cell::new(CellState::Dead, kind), Some(Transition::AgedOut))
+                    }
+                    // Underpopulation - prey dies if not enough prey around to survive
+                    else if own < under {
+                        (Cell::new(CellState::Dead, kind), Some(Transition::Isolated))
+                    }
+                    // Overpopulation - prey dies due to overcrowding
+                    else if own > over {
+                        (
+                            Cell::new(CellState::Dead, kind),
+                            Some(Transition::Overcrowded),
+                        )
+                    }
+                    // Prey reproduction - breed new prey based on reproduction conditions,
+                    // more often on fertile ground
+                    else if births
+                        && own >= reprod
+                        && own <= over
+                        && rng.gen_bool((genome.reproduction_chance() * fertility).min(1.0))
+                    {
+                        let genome = genome.mutate(gene_mutation, rng);
+                        (
+                            child(kind, genome, &self.rules), // Newborn prey
+                            Some(Transition::Born),
+                        )
+                    }
+                    // Stay Alive - prey survives under normal conditions
+                    else if own >= survival && own <= over {
+                        (
+                            cell.with_state(CellState::Alive {
+                                age: age + 1,
+                                energy: 0,
+                                health,
+                            }),
+                            Some(Transition::Survived),
+                        )
+                    } else {
+                        (cell, Some(Transition::Survived))
+                    }
+                } else {
+                    // A predator pays for the newborn it had in the hunt before
+                    // this step, burns energy and gains some if it caught prey
+                    let (fed, bred) = match hunt {
+                        Hunt::Predator { fed, bred } => (fed, bred),
+                        _ => (false, false),
+                    };
+                    let mut energy = energy;
+                    if bred {
+                        energy = energy.saturating_sub(rules.birth_energy);
+                    }
+                    energy = energy.saturating_sub(rules.energy_loss);
+                    if fed {
+                        energy += rules.energy_gain;
+                    }
+
+                    // Age - predator dies of old age
+                    if age >= max_age {
+                        (Cell::new(CellState::Dead, kind), Some(Transition::AgedOut))
+                    }
+                    // Predator dies due to underpopulation - not enough other predators around
+                    else if own < under {
+                        (Cell::new(CellState::Dead, kind), Some(Transition::Isolated))
+                    }
+                    // Predator dies due to overpopulation - too many predators in one area
+                    else if own > over {
+                        (
+                            Cell::new(CellState::Dead, kind),
+                            Some(Transition::Overcrowded),
+                        )
+                    }
+                    // Predator dies of hunger once its energy runs out
+                    else if energy == 0 {
+                        (Cell::new(CellState::Dead, kind), Some(Transition::Starved))
+                    }
+                    // No food, predator ages faster and lives off its energy
+                    else if food == 0 {
+                        (
+                            cell.with_state(CellState::Alive {
+                                age: age + aging,
+                                energy,
+                                health,
+                            }),
+                            Some(Transition::Survived),
+                        )
+                    }
+                    // Predator survives with prey around it, its newborns were
+                    // placed in the hunt
+                    else {
+                        (
+                            cell.with_state(CellState::Alive {
+                                age: age + 1,
+                                energy,
+                                health,
+                            }),
+                            Some(Transition::Survived),
+                        )
                     }
                 }
             }
             CellState::Dead => {
-                // Prey reproduction or mutation - spawn new prey if conditions are met
-                if prey == reprod || mutation {
-                    Cell::prey(cell.x, cell.y, CellState::Alive(1)) // Newborn or mutated prey
-                }
-                // Predator reproduction or mutation - spawn new predator if conditions are met
-                else if predators > 0 && prey == 0 || mutation {
-                    Cell::prededator(cell.x, cell.y, CellState::Alive(1)) // Newborn or mutated predator
+                // Prey reproduction - the first species that doesn't hunt with
+                // exactly its reproduction threshold around breeds here
+                let producer = self.config.web.kinds().find(|&kind| {
+                    let rules = &self.rules[kind.index()];
+                    let threshold = neighbours.of(kind) == rules.reproduction;
+                    births && grazed && !rules.is_predator() && threshold
+                });
+
+                // Predator reproduction - a predator around picked this cell
+                // for its newborn in the hunt, see `predation::hunt`
+                if let Hunt::Born(kind) = hunt {
+                    let genome = inherit(kind, rng);
+                    (
+                        child(kind, genome, &self.rules), // Newborn predator
+                        Some(Transition::Born),
+                    )
+                } else if let Some(kind) = producer {
+                    let genome = inherit(kind, rng);
+                    (
+                        child(kind, genome, &self.rules), // Newborn prey
+                        Some(Transition::Born),
+                    )
+                } else if mutation && grazed && births {
+                    (
+                        newborn(CellType::PREY, &self.rules), // Mutated first species
+                        Some(Transition::Mutated),
+                    )
                 }
                 // Stay dead if no reproduction or mutation occurs
                 else {
-                    Cell::new(cell.x, cell.y, cell.state, cell.kind)
+                    (cell, None)
                 }
             }
+        }
+    }
+
+    // The epidemic in a cell that survived the rules. An infected cell may
+    // die of it or recover for good, otherwise it ages faster; a susceptible
+    // one catches it from each infected neighbour in turn, less easily from
+    // other species.
+    fn infect(
+        &self,
+        (cell, transition): (Cell, Option<Transition>),
+        neighbours: Neighbours,
+        rng: &mut StdRng,
+    ) -> (Cell, Option<Transition>) {
+        let config = &self.config;
+        if config.infection == 0.0 || transition != Some(Transition::Survived) {
+            return (cell, transition);
+        }
+        let CellState::Alive {
+            age,
+            energy,
+            health,
+        } = cell.state()
+        else {
+            return (cell, transition);
+        };
+        match health {
+            Health::Infected if rng.gen_bool(config.virulence) => (
+                Cell::new(CellState::Dead, cell.kind()),
+                Some(Transition::Diseased),
+            ),
+            Health::Infected if rng.gen_bool(config.recovery) => (
+                cell.with_health(Health::Recovered),
+                Some(Transition::Recovered),
+            ),
+            Health::Infected => (
+                cell.with_state(CellState::Alive {
+                    age: age + config.sick_aging,
+                    energy,
+                    health,
+                }),
+                transition,
+            ),
+            Health::Susceptible => {
+                let own = neighbours.infected(cell.kind());
+                let other = neighbours.all_infected() - own;
+                let escape = (1.0 - config.infection).powi(own as i32)
+                    * (1.0 - (config.infection * config.cross_infection).min(1.0))
+                        .powi(other as i32);
+                if rng.gen_bool(1.0 - escape) {
+                    (
+                        cell.with_health(Health::Infected),
+                        Some(Transition::Infected),
+                    )
+                } else {
+                    (cell, transition)
+                }
+            }
+            Health::Recovered => (cell, transition),
+        }
+    }
+
+    // Resources under `map`, which the step produced, for its generation.
+    // Written into `next` to reuse the grids of the spare state.
+    fn grow_resources(&self, map: &Grid<Cell>, next: &mut Option<Resources>) {
+        let Some(resources) = self.state.resources.as_ref() else {
+            *next = None;
+            return;
         };
+        let grazed = |x, y| {
+            let cell = map[(x, y)];
+            cell.is_alive() && !self.rules[cell.kind().index()].is_predator()
+        };
+        resources.grow(
+            next.get_or_insert_with(Resources::default),
+            grazed,
+            self.config.grazing,
+            self.config.diffusion,
+            self.config.regrowth,
+        );
     }
 
-    fn count_neighbours(&self, x: usize, y: usize) -> (usize, usize) {
-        let mut preys = 0;
-        let mut predators = 0;
+    // Whether any species hunts, without predators there is no hunt to settle
+    fn has_predators(&self) -> bool {
+        self.config.rule.is_none() && self.rules.iter().any(Rules::is_predator)
+    }
 
-        for (dx, dy) in self.state.offsets.iter() {
-            let nx = x.wrapping_add(*dx as usize);
-            let ny = y.wrapping_add(*dy as usize);
-            if nx < self.config.w && ny < self.config.h {
-                if let CellState::Alive(_) = self.state.map[ny][nx].state {
-                    if let CellType::Prey = self.state.map[ny][nx].kind {
-                        preys += 1;
-                    } else {
-                        predators += 1;
-                    }
+    // Settles predation on the grid ahead of the step to `generation` into
+    // `hunts`, which is left empty when nothing hunts
+    fn hunt_grid(&mut self, generation: usize) {
+        if !self.has_predators() {
+            self.hunts = Grid::default();
+            return;
+        }
+        let map = &self.state.map;
+        // Reuse last generation's grid
+        let mut hunts = std::mem::take(&mut self.hunts);
+        if hunts.width() == map.width() && hunts.height() == map.height() {
+            hunts.fill(Hunt::None);
+        } else {
+            hunts = Grid::new(map.width(), map.height(), Hunt::None);
+        }
+        let mut hunters = Vec::new();
+        for (y, row) in map.rows().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                hunters.extend(hunter((x as i64, y as i64), *cell, &self.rules));
+            }
+        }
+        let catchable = |kind, x: i64, y: i64| {
+            let (Ok(x), Ok(y)) = (usize::try_from(x), usize::try_from(y)) else {
+                return false;
+            };
+            // Refuges hide whatever lives in them
+            let refuge = self.config.terrain.get(x, y) == Ground::Refuge;
+            !refuge
+                && map.get(x, y).is_some_and(|cell| {
+                    self.is_catchable(kind, *cell, || {
+                        self.count_neighbours(map, &self.counts, x, y)
+                    })
+                })
+        };
+
+        let vacant = |x: i64, y: i64| {
+            let (Ok(x), Ok(y)) = (usize::try_from(x), usize::try_from(y)) else {
+                return false;
+            };
+            map.get(x, y).is_some_and(|cell| !cell.is_alive())
+                && self.config.terrain.get(x, y).allows_births()
+        };
+
+        for ((x, y), hunt) in self.hunt(&hunters, catchable, vacant, generation) {
+            hunts[(x as usize, y as usize)] = hunt;
+        }
+        self.hunts = hunts;
+    }
+
+    // Settles predation on the plane, split into the tiles it happened in
+    fn hunt_world(&self, world: &World, generation: usize) -> HashMap<TileKey, Grid<Hunt>> {
+        if !self.has_predators() {
+            return HashMap::new();
+        }
+        let hunters: Vec<_> = world
+            .live_cells()
+            .into_iter()
+            .filter_map(|(pos, cell)| hunter(pos, cell, &self.rules))
+            .collect();
+        let catchable = |kind, x: i64, y: i64| {
+            self.is_catchable(kind, world.get(x, y), || self.world_neighbours(world, x, y))
+        };
+
+        let vacant = |x: i64, y: i64| !world.get(x, y).is_alive();
+
+        let mut hunts = HashMap::new();
+        for ((x, y), hunt) in self.hunt(&hunters, catchable, vacant, generation) {
+            let (key, lx, ly) = world::split(x, y);
+            hunts
+                .entry(key)
+                .or_insert_with(|| Grid::new(TILE, TILE, Hunt::None))[(lx, ly)] = hunt;
+        }
+        hunts
+    }
+
+    fn hunt(
+        &self,
+        hunters: &[Hunter],
+        catchable: impl FnMut(CellType, i64, i64) -> bool,
+        vacant: impl FnMut(i64, i64) -> bool,
+        generation: usize,
+    ) -> Vec<((i64, i64), Hunt)> {
+        let mut rng = hunt_rng(self.config.seed, generation);
+        // Hunters can't reach past walls
+        let terrain = self.config.terrain;
+        let sight = |(x, y): (i64, i64), (dx, dy)| {
+            let (x, y) = (x as usize, y as usize);
+            !terrain.walls_near(x, y, self.config.radius) || !terrain.blocks(x, y, dx, dy)
+        };
+        predation::hunt(
+            hunters,
+            self.state.offsets,
+            sight,
+            catchable,
+            vacant,
+            self.config.offspring,
+            self.config.escape,
+            &mut rng,
+        )
+    }
+
+    // Moves the live cells of `map`, which the step to `generation` produced
+    fn move_grid(&mut self, map: &mut Grid<Cell>, generation: usize) {
+        if !self.config.movement || self.config.rule.is_some() {
+            return;
+        }
+        // The counts of the current state aren't needed again this step
+        let mut counts = std::mem::take(&mut self.counts);
+        if self.config.shape == Shape::Moore {
+            counts.build(map, &self.rules, self.config.infection > 0.0);
+        }
+        let movers: Vec<_> = map
+            .rows()
+            .enumerate()
+            .flat_map(|(y, row)| {
+                row.iter()
+                    .enumerate()
+                    .filter(|(_, cell)| cell.is_alive())
+                    .map(move |(x, cell)| Mover {
+                        pos: (x as i64, y as i64),
+                        kind: cell.kind(),
+                    })
+            })
+            .collect();
+        let is_empty = |x: i64, y: i64| {
+            let (Ok(x), Ok(y)) = (usize::try_from(x), usize::try_from(y)) else {
+                return false;
+            };
+            let wall = self.config.terrain.get(x, y) == Ground::Wall;
+            !wall && map.get(x, y).is_some_and(|cell| !cell.is_alive())
+        };
+        let score = |kind, x: i64, y: i64| {
+            self.score(
+                kind,
+                self.count_neighbours(map, &counts, x as usize, y as usize),
+            )
+        };
+        let mut rng = move_rng(self.config.seed, generation);
+        let moves = movement::plan(&movers, is_empty, score, &mut rng);
+
+        for ((fx, fy), (tx, ty)) in moves {
+            let from = (fx as usize, fy as usize);
+            map[(tx as usize, ty as usize)] = map[from];
+            map[from] = Cell::default();
+        }
+        self.counts = counts;
+    }
+
+    // Moves the live cells of `world`, which the step to `generation` produced
+    fn move_world(&self, world: &mut World, generation: usize) {
+        if !self.config.movement || self.config.rule.is_some() {
+            return;
+        }
+        let movers: Vec<_> = world
+            .live_cells()
+            .into_iter()
+            .map(|(pos, cell)| Mover {
+                pos,
+                kind: cell.kind(),
+            })
+            .collect();
+        let is_empty = |x: i64, y: i64| !world.get(x, y).is_alive();
+        let score = |kind, x: i64, y: i64| self.score(kind, self.world_neighbours(world, x, y));
+        let mut rng = move_rng(self.config.seed, generation);
+        let moves = movement::plan(&movers, is_empty, score, &mut rng);
+
+        for ((fx, fy), (tx, ty)) in moves {
+            let cell = world.get(fx, fy);
+            world.set(fx, fy, Cell::default());
+            world.set(tx, ty, cell);
+        }
+    }
+
+    // How much a mover of `kind` wants to be somewhere with `neighbours`
+    // around it. Cells follow what they eat and keep away from what eats them.
+    fn score(&self, kind: CellType, neighbours: Neighbours) -> i64 {
+        let rules = &self.rules[kind.index()];
+        neighbours.sum(rules.food) as i64 - neighbours.sum(rules.eaters) as i64
+    }
+
+    // A cell `hunter` eats can be caught once the web's resistence plus its own
+    // in hunters are around it
+    fn is_catchable(
+        &self,
+        hunter: CellType,
+        cell: Cell,
+        neighbours: impl FnOnce() -> Neighbours,
+    ) -> bool {
+        cell.is_alive()
+            && self
+                .config
+                .web
+                .resistence(hunter, cell.kind())
+                .is_some_and(|resistence| {
+                    neighbours().of(hunter) >= resistence + cell.genome().resistence
+                })
+    }
+
+    // Genome of a newborn of `kind` at (x, y) of `map`, crossed from two live
+    // neighbours of that species picked at random and mutated. Predators only
+    // breed once they have the energy, so only those count as parents, and
+    // walls hide the ones behind them. Without parents the newborn gets the
+    // genome of the species' rules.
+    fn inherit(
+        &self,
+        map: &Grid<Cell>,
+        x: usize,
+        y: usize,
+        kind: CellType,
+        rng: &mut StdRng,
+    ) -> Genome {
+        let mut parents = [None, None];
+        let mut seen = 0;
+        let terrain = self.config.terrain;
+        let walls = terrain.walls_near(x, y, self.config.radius);
+        for &(dx, dy) in self.state.offsets.iter() {
+            if walls && terrain.blocks(x, y, dx, dy) {
+                continue;
+            }
+            let nx = x.wrapping_add(dx as usize);
+            let ny = y.wrapping_add(dy as usize);
+            let Some(neighbour) = map.get(nx, ny) else {
+                continue;
+            };
+            let rules = &self.rules[kind.index()];
+            let fertile = match neighbour.state() {
+                CellState::Alive { energy, .. } => {
+                    !rules.is_predator() || energy >= rules.breed_energy
+                }
+                CellState::Dead => false,
+            };
+            if !fertile || neighbour.kind() != kind {
+                continue;
+            }
+            // Each parent is kept with chance 1/seen, which leaves it uniform
+            // over every candidate
+            seen += 1;
+            for parent in &mut parents {
+                if rng.gen_range(0..seen) == 0 {
+                    *parent = Some(neighbour.genome());
                 }
             }
         }
-        (preys, predators)
+        match parents {
+            [Some(a), Some(b)] => a.cross(&b, rng).mutate(self.config.gene_mutation, rng),
+            _ => self.rules[kind.index()].genome(),
+        }
+    }
+
+    // Live cells around (x, y) of an infinite world
+    fn world_neighbours(&self, world: &World, x: i64, y: i64) -> Neighbours {
+        let mut neighbours = Neighbours::default();
+        for &(dx, dy) in self.state.offsets {
+            let neighbour = world.get(x + dx as i64, y + dy as i64);
+            neighbours.add(neighbour);
+        }
+        neighbours
+    }
+
+    // Live cells around (x, y) of `map`, `counts` must be built from `map`
+    // for square neighbourhoods
+    fn count_neighbours(
+        &self,
+        map: &Grid<Cell>,
+        counts: &SummedArea,
+        x: usize,
+        y: usize,
+    ) -> Neighbours {
+        // Walls block the cells behind them, which the square counts
+        let terrain = self.config.terrain;
+        let walls = terrain.walls_near(x, y, self.config.radius);
+        if self.config.shape == Shape::Moore && !walls {
+            let mut neighbours = counts.square(x, y, self.config.radius);
+            // The square includes the cell itself
+            neighbours.remove(map[(x, y)]);
+            return neighbours;
+        }
+
+        let mut neighbours = Neighbours::default();
+        for &(dx, dy) in self.state.offsets.iter() {
+            if walls && terrain.blocks(x, y, dx, dy) {
+                continue;
+            }
+            let nx = x.wrapping_add(dx as usize);
+            let ny = y.wrapping_add(dy as usize);
+            if let Some(neighbour) = map.get(nx, ny) {
+                neighbours.add(*neighbour);
+            }
+        }
+        neighbours
     }
 }
 
-pub fn generate_ring_offsets(radius: isize) -> Vec<(isize, isize)> {
+const NEWBORN: CellState = CellState::Alive {
+    age: 1,
+    energy: 0,
+    health: Health::Susceptible,
+};
+
+// What lies under a cell being stepped
+#[derive(Debug, Clone, Copy, Default)]
+struct Site {
+    ground: Ground,
+    // Resource level, None without the resource layer
+    resource: Option<f64>,
+}
+
+// A cell of `kind` with its species' genome in its first generation
+fn newborn(kind: CellType, rules: &[Rules]) -> Cell {
+    child(kind, rules[kind.index()].genome(), rules)
+}
+
+// A cell of `kind` with `genome` in its first generation, predators start with
+// their species' `birth_energy`
+fn child(kind: CellType, genome: Genome, rules: &[Rules]) -> Cell {
+    let rules = &rules[kind.index()];
+    let energy = if rules.is_predator() {
+        rules.birth_energy
+    } else {
+        0
+    };
+    let state = CellState::Alive {
+        age: 1,
+        energy,
+        health: Health::Susceptible,
+    };
+    Cell::new(state, kind).with_genome(genome)
+}
+
+// `cell` at `pos` as a hunter if it is a live predator
+fn hunter(pos: (i64, i64), cell: Cell, rules: &[Rules]) -> Option<Hunter> {
+    let kind = cell.kind();
+    match cell.state() {
+        CellState::Alive { energy, .. } if rules[kind.index()].is_predator() => Some(Hunter {
+            pos,
+            kind,
+            energy,
+            breeder: energy >= rules[kind.index()].breed_energy,
+            genome: cell.genome(),
+        }),
+        _ => None,
+    }
+}
+
+// Species of a cell of the initial map, drawn by the weights in `shares`
+fn pick_species(shares: &[f64], total: f64, rng: &mut StdRng) -> CellType {
+    let mut roll = rng.gen_range(0.0..total.max(f64::MIN_POSITIVE));
+    for (kind, share) in shares.iter().enumerate() {
+        if roll < *share {
+            return CellType(kind as u8);
+        }
+        roll -= share;
+    }
+    CellType::PREY
+}
+
+// Frame time auto frame skip aims for when the frame rate is uncapped
+const AUTO_FRAME: Duration = Duration::from_millis(33);
+
+// None when uncapped
+fn frame_duration(fps: usize) -> Option<Duration> {
+    (fps > 0).then(|| Duration::from_secs_f64(1.0 / fps as f64))
+}
+
+// Each generation draws from its own stream so any generation can be
+// re-simulated on its own given the state before it
+fn generation_rng(seed: u64, generation: usize) -> StdRng {
+    StdRng::seed_from_u64(seed ^ (generation as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+}
+
+fn row_rng(seed: u64, generation: usize, row: usize) -> StdRng {
+    let row = (row as u64 + 1).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    StdRng::seed_from_u64(
+        seed ^ (generation as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ row.rotate_left(31),
+    )
+}
+
+// Predation is settled over the whole map at once before the rows or tiles
+// are stepped, so it draws from its own stream
+fn hunt_rng(seed: u64, generation: usize) -> StdRng {
+    generation_rng(seed ^ 0x5851_F42D_4C95_7F2D, generation)
+}
+
+// Likewise for the movement phase after the step
+fn move_rng(seed: u64, generation: usize) -> StdRng {
+    generation_rng(seed ^ 0x2545_F491_4F6C_DD1D, generation)
+}
+
+// Cells skipped before the next mutation when each mutates with probability `p`
+fn mutation_gap(p: f64, rng: &mut StdRng) -> usize {
+    if p >= 1.0 {
+        0
+    } else if p <= 0.0 {
+        usize::MAX
+    } else {
+        let u: f64 = rng.gen();
+        ((-u).ln_1p() / (-p).ln_1p()).floor() as usize
+    }
+}
+
+fn tile_rng(seed: u64, generation: usize, (tx, ty): TileKey) -> StdRng {
+    let tile = (tx as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+        ^ (ty as u64)
+            .wrapping_mul(0x1656_67B1_9E37_79F9)
+            .rotate_left(17);
+    StdRng::seed_from_u64(
+        seed ^ (generation as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ tile.rotate_left(31),
+    )
+}
+
+pub fn generate_ring_offsets(radius: isize, shape: Shape) -> Vec<(isize, isize)> {
     let mut offsets = Vec::new();
     for dx in -radius..=radius {
         for dy in -radius..=radius {
             if dx == 0 && dy == 0 {
                 continue;
             }
+            let inside = match shape {
+                Shape::Moore => true,
+                Shape::VonNeumann => dx.abs() + dy.abs() <= radius,
+                Shape::Circle => dx * dx + dy * dy <= radius * radius,
+            };
             // if dx.abs() == radius || dy.abs() == radius {
-            offsets.push((dx, dy));
+            if inside {
+                offsets.push((dx, dy));
+            }
             // }
         }
     }
     offsets
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_config() -> Config {
+        Config {
+            w: 48,
+            h: 32,
+            population: 30,
+            cluster_size: 4,
+            predetor_rate: 0.2,
+            seed: 11,
+            ..Config::default()
+        }
+    }
+
+    // The map after each of `generations` steps, starting with the initial one
+    fn run(game: &mut GameOfLife, generations: usize) -> Vec<Grid<Cell>> {
+        let mut maps = vec![game.state.map.clone()];
+        for _ in 0..generations {
+            game.step();
+            maps.push(game.state.map.clone());
+        }
+        maps
+    }
+
+    // The cells on screen, whichever engine holds them
+    fn cells(game: &GameOfLife) -> Grid<Cell> {
+        let mut map = Grid::new(game.state.w, game.state.h, Cell::default());
+        for y in 0..game.state.h {
+            for x in 0..game.state.w {
+                map[(x, y)] = game.state.cell(game.universe.as_ref(), x, y);
+            }
+        }
+        map
+    }
+
+    // The next map computed cell by cell from the neighbourhood offsets, the
+    // reference the faster engines have to match, and what happened in it.
+    // Wakes every tile so both roll the same random numbers.
+    fn reference_step(game: &mut GameOfLife) -> (Grid<Cell>, Census) {
+        game.state.activity = None;
+        let generation = game.state.generation + 1;
+        game.season = game.config.schedule.season(&game.config, generation);
+        game.rules = game.config.web.rules(&game.season);
+        // The hunt is settled the same way for both
+        let disease = game.config.infection > 0.0;
+        game.counts.build(&game.state.map, &game.rules, disease);
+        game.hunt_grid(generation);
+        let map = &cells(game);
+        let mut next = map.clone();
+        let mut census = Census::default();
+        for y in 0..map.height() {
+            let mut rng = row_rng(game.config.seed, generation, y);
+            for x in 0..map.width() {
+                let mut neighbours = Neighbours::default();
+                for &(dx, dy) in game.config.offsets {
+                    let nx = x.wrapping_add(dx as usize);
+                    let ny = y.wrapping_add(dy as usize);
+                    if let Some(neighbour) = map.get(nx, ny) {
+                        neighbours.add(*neighbour);
+                    }
+                }
+                let site = Site {
+                    ground: game.config.terrain.get(x, y),
+                    resource: game.state.resources.as_ref().map(|r| r.level(x, y)),
+                };
+                next[(x, y)] = game.step_cell(
+                    map[(x, y)],
+                    neighbours,
+                    game.hunts.get(x, y).copied().unwrap_or_default(),
+                    site,
+                    |kind, rng: &mut StdRng| game.inherit(map, x, y, kind, rng),
+                    game.season.mutation,
+                    &mut rng,
+                    &mut census,
+                );
+            }
+        }
+        (next, census)
+    }
+
+    // The next world computed like `reference_step`, tile by tile with the
+    // random numbers of each tile, counting neighbours across tile edges
+    fn reference_world_step(game: &mut GameOfLife) -> Vec<((i64, i64), Cell)> {
+        let generation = game.state.generation + 1;
+        game.season = game.config.schedule.season(&game.config, generation);
+        game.rules = game.config.web.rules(&game.season);
+        let world = game.state.world.as_ref().unwrap();
+        let hunts = game.hunt_world(world, generation);
+        let radius = game.config.radius;
+        let mut next = World::default();
+        for key in world.active(radius.div_ceil(TILE) as i64) {
+            let (left, top) = (key.0 * TILE as i64, key.1 * TILE as i64);
+            // The tile and everything within the radius around it
+            let mut local = Grid::new(TILE + 2 * radius, TILE + 2 * radius, Cell::default());
+            for y in 0..local.height() {
+                for x in 0..local.width() {
+                    let (wx, wy) = (left + x as i64, top + y as i64);
+                    local[(x, y)] = world.get(wx - radius as i64, wy - radius as i64);
+                }
+            }
+            let mutation = if world.contains(key) {
+                game.season.mutation
+            } else {
+                0.0
+            };
+            let mut rng = tile_rng(game.config.seed, generation, key);
+            let mut tile = Grid::new(TILE, TILE, Cell::default());
+            for y in 0..TILE {
+                for x in 0..TILE {
+                    let (lx, ly) = (x + radius, y + radius);
+                    let mut neighbours = Neighbours::default();
+                    for &(dx, dy) in game.config.offsets {
+                        let (nx, ny) = (lx as isize + dx, ly as isize + dy);
+                        neighbours.add(local[(nx as usize, ny as usize)]);
+                    }
+                    tile[(x, y)] = game.step_cell(
+                        local[(lx, ly)],
+                        neighbours,
+                        hunts.get(&key).map_or(Hunt::None, |hunts| hunts[(x, y)]),
+                        Site::default(),
+                        |kind, rng: &mut StdRng| game.inherit(&local, lx, ly, kind, rng),
+                        mutation,
+                        &mut rng,
+                        &mut Census::default(),
+                    );
+                }
+            }
+            next.insert(key, tile);
+        }
+        next.live_cells()
+    }
+
+    #[test]
+    fn summed_area_steps_match_the_reference() {
+        for radius in [1, 3] {
+            let config = Config {
+                radius,
+                infection: 0.3,
+                infected: 0.2,
+                ..small_config()
+            };
+            let mut game = GameOfLife::new(config.with_offsets());
+            for _ in 0..15 {
+                let (expected, _) = reference_step(&mut game);
+                game.step();
+                assert!(game.state.map == expected, "radius {radius}");
+            }
+            assert!(game.state.map.iter().any(Cell::is_alive));
+        }
+    }
+
+    #[test]
+    fn bit_engine_matches_the_reference() {
+        // Widths across word boundaries, B0 rules wake the whole grid
+        for (w, rule) in [
+            (64, "B3/S23"),
+            (100, "B36/S23"),
+            (130, "B2/S"),
+            (70, "B0/S8"),
+        ] {
+            let config = Config {
+                w,
+                rule: Some(rule.parse().unwrap()),
+                ..small_config()
+            };
+            let mut game = GameOfLife::new(config);
+            assert!(game.state.bits.is_some());
+            for _ in 0..10 {
+                let (expected, census) = reference_step(&mut game);
+                game.step();
+                assert!(cells(&game) == expected, "{rule} on {w} columns");
+                assert_eq!(
+                    game.state.census.summary(1, false),
+                    census.summary(1, false)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn world_steps_match_the_reference() {
+        // Big enough to spread over several tiles
+        for radius in [1, 3] {
+            let config = Config {
+                w: 100,
+                h: 50,
+                population: 80,
+                radius,
+                infinite: true,
+                infection: 0.3,
+                infected: 0.2,
+                ..small_config()
+            };
+            let mut game = GameOfLife::new(config.with_offsets());
+            for _ in 0..5 {
+                let expected = reference_world_step(&mut game);
+                game.step();
+                let world = game.state.world.as_ref().unwrap();
+                assert!(world.live_cells() == expected, "radius {radius}");
+            }
+            assert!(game.state.world.as_ref().unwrap().tiles() > 1);
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn threads_step_like_a_single_one() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        for infinite in [false, true] {
+            let config = Config {
+                w: 100,
+                h: 50,
+                population: 80,
+                infinite,
+                infection: 0.3,
+                infected: 0.2,
+                ..small_config()
+            };
+            let mut serial = GameOfLife::new(Config {
+                threads: 1,
+                ..config
+            });
+            let mut parallel = GameOfLife::new(Config {
+                threads: 4,
+                ..config
+            });
+            for _ in 0..10 {
+                serial.step();
+                pool.install(|| parallel.step());
+                assert!(cells(&parallel) == cells(&serial), "infinite {infinite}");
+                let live = |game: &GameOfLife| game.state.world.as_ref().map(World::live_cells);
+                assert!(live(&parallel) == live(&serial), "infinite {infinite}");
+            }
+        }
+    }
+
+    #[test]
+    fn quiet_tiles_step_like_busy_ones() {
+        // B/S rules wider than the bit engine takes, so changes have to wake
+        // the tiles across an edge
+        for radius in [2, 17] {
+            let config = Config {
+                w: 160,
+                h: 64,
+                population: 3,
+                radius,
+                rule: Some("B4/S4567".parse().unwrap()),
+                ..small_config()
+            };
+            let mut quiet = GameOfLife::new(config.with_offsets());
+            let mut busy = GameOfLife::new(config.with_offsets());
+            for _ in 0..20 {
+                quiet.step();
+                busy.state.activity = None;
+                busy.step();
+                assert!(quiet.state.map == busy.state.map, "radius {radius}");
+            }
+            let (active, tiles) = quiet.state.activity.as_ref().unwrap().count();
+            assert!(active < tiles, "radius {radius}");
+        }
+    }
+
+    #[test]
+    fn mutation_gaps_follow_the_chance() {
+        let mut rng = StdRng::seed_from_u64(3);
+        assert_eq!(mutation_gap(0.0, &mut rng), usize::MAX);
+        assert_eq!(mutation_gap(1.0, &mut rng), 0);
+        assert_eq!(mutation_gap(1e-300, &mut rng), usize::MAX);
+
+        // Geometric with mean (1 - p) / p
+        let samples = 20_000;
+        let mean = (0..samples)
+            .map(|_| mutation_gap(0.25, &mut rng))
+            .sum::<usize>() as f64
+            / samples as f64;
+        assert!((mean - 3.0).abs() < 0.1, "mean gap {mean}");
+    }
+
+    #[test]
+    fn breeders_pay_for_each_newborn() {
+        let config = Config {
+            w: 9,
+            h: 9,
+            population: 0,
+            mutation: 0.0,
+            underpopulation: 0,
+            ..small_config()
+        };
+        let mut game = GameOfLife::new(config);
+        let state = CellState::Alive {
+            age: 1,
+            energy: 20,
+            health: Health::Susceptible,
+        };
+        // Breeds whenever it has the energy
+        let genome = Genome::new(100, 1.0, 1, 5);
+        let parent = Cell::new(state, CellType::PREDETOR).with_genome(genome);
+        game.state.set(4, 4, parent);
+
+        // Birth energy 6 and 1 burnt a generation, the second newborn leaves
+        // the parent too little to breed again
+        for (energy, population) in [(13, 2), (6, 3), (5, 3)] {
+            game.step();
+            let CellState::Alive { energy: left, .. } = game.state.map[(4, 4)].state() else {
+                panic!("the parent died");
+            };
+            assert_eq!(left, energy);
+            let predators = game.state.map.iter().filter(|cell| cell.is_alive());
+            assert_eq!(predators.count(), population);
+        }
+    }
+
+    // A predator with `energy` left that never breeds, in an otherwise empty world
+    fn lone_predator(energy: usize) -> GameOfLife {
+        let config = Config {
+            w: 9,
+            h: 9,
+            population: 0,
+            mutation: 0.0,
+            underpopulation: 0,
+            escape: 0.0,
+            ..small_config()
+        };
+        let mut game = GameOfLife::new(config);
+        let state = CellState::Alive {
+            age: 1,
+            energy,
+            health: Health::Susceptible,
+        };
+        let genome = Genome::new(100, 0.0, 0, 5);
+        game.state.set(
+            4,
+            4,
+            Cell::new(state, CellType::PREDETOR).with_genome(genome),
+        );
+        game
+    }
+
+    fn energy(cell: Cell) -> Option<usize> {
+        match cell.state() {
+            CellState::Alive { energy, .. } => Some(energy),
+            CellState::Dead => None,
+        }
+    }
+
+    #[test]
+    fn predators_catch_prey_next_to_them() {
+        let mut game = lone_predator(5);
+        let state = CellState::Alive {
+            age: 1,
+            energy: 0,
+            health: Health::Susceptible,
+        };
+        let prey = Cell::prey(state).with_genome(Genome::new(100, 0.0, 0, 5));
+        game.state.set(5, 4, prey);
+        game.step();
+        assert!(!game.state.map[(5, 4)].is_alive());
+        // 1 burnt and 3 gained from the meal
+        assert_eq!(energy(game.state.map[(4, 4)]), Some(7));
+        assert_eq!(game.state.census.get(CellType::PREY, Transition::Eaten), 1);
+    }
+
+    #[test]
+    fn unfed_predators_starve() {
+        let mut game = lone_predator(2);
+        game.step();
+        assert_eq!(energy(game.state.map[(4, 4)]), Some(1));
+        game.step();
+        assert_eq!(energy(game.state.map[(4, 4)]), None);
+        let census = &game.state.census;
+        assert_eq!(census.get(CellType::PREDETOR, Transition::Starved), 1);
+    }
+
+    fn sick(kind: CellType, health: Health) -> Cell {
+        let state = CellState::Alive {
+            age: 10,
+            energy: 0,
+            health,
+        };
+        Cell::new(state, kind)
+    }
+
+    // Runs only the epidemic on `cell` with `infected` of each species around it
+    fn infect(
+        config: Config,
+        cell: Cell,
+        transition: Transition,
+        infected: [usize; 2],
+    ) -> (Cell, Option<Transition>) {
+        let game = GameOfLife::new(Config {
+            population: 0,
+            ..config
+        });
+        let mut neighbours = Neighbours::default();
+        for (kind, &count) in infected.iter().enumerate() {
+            for _ in 0..count {
+                neighbours.add(sick(CellType(kind as u8), Health::Infected));
+            }
+        }
+        let mut rng = StdRng::seed_from_u64(5);
+        game.infect((cell, Some(transition)), neighbours, &mut rng)
+    }
+
+    #[test]
+    fn epidemic_infects_kills_and_recovers() {
+        let config = Config {
+            infection: 1.0,
+            cross_infection: 0.0,
+            virulence: 0.0,
+            recovery: 0.0,
+            sick_aging: 3,
+            ..small_config()
+        };
+        let survived = Transition::Survived;
+        let prey = |health| sick(CellType::PREY, health);
+
+        // Caught from its own species, not from the other one without cross infection
+        let (cell, transition) = infect(config, prey(Health::Susceptible), survived, [1, 0]);
+        assert_eq!(transition, Some(Transition::Infected));
+        assert_eq!(cell, prey(Health::Infected));
+        let (cell, transition) = infect(config, prey(Health::Susceptible), survived, [0, 3]);
+        assert_eq!(
+            (cell, transition),
+            (prey(Health::Susceptible), Some(survived))
+        );
+        let cross = Config {
+            cross_infection: 1.0,
+            ..config
+        };
+        let (_, transition) = infect(cross, prey(Health::Susceptible), survived, [0, 1]);
+        assert_eq!(transition, Some(Transition::Infected));
+
+        // The infected age faster until they die or recover
+        let (cell, transition) = infect(config, prey(Health::Infected), survived, [0, 0]);
+        assert_eq!(transition, Some(survived));
+        assert!(matches!(cell.state(), CellState::Alive { age: 13, .. }));
+        let deadly = Config {
+            virulence: 1.0,
+            ..config
+        };
+        let (cell, transition) = infect(deadly, prey(Health::Infected), survived, [0, 0]);
+        assert_eq!(transition, Some(Transition::Diseased));
+        assert!(!cell.is_alive());
+        let mild = Config {
+            recovery: 1.0,
+            ..config
+        };
+        let (cell, transition) = infect(mild, prey(Health::Infected), survived, [0, 0]);
+        assert_eq!(transition, Some(Transition::Recovered));
+        assert_eq!(cell, prey(Health::Recovered));
+
+        // Recovered cells stay immune, and only survivors take part
+        let (cell, _) = infect(config, prey(Health::Recovered), survived, [8, 0]);
+        assert_eq!(cell, prey(Health::Recovered));
+        let born = Transition::Born;
+        let (cell, transition) = infect(config, prey(Health::Susceptible), born, [8, 0]);
+        assert_eq!((cell, transition), (prey(Health::Susceptible), Some(born)));
+    }
+
+    #[test]
+    fn cross_infection_is_a_chance_however_large() {
+        let config = Config {
+            infection: 0.8,
+            cross_infection: 2.0,
+            ..small_config()
+        };
+        let susceptible = sick(CellType::PREY, Health::Susceptible);
+        let (_, transition) = infect(config, susceptible, Transition::Survived, [0, 1]);
+        assert_eq!(transition, Some(Transition::Infected));
+    }
+
+    #[test]
+    fn walls_hide_what_is_behind_them_like_the_edge() {
+        // The left half of a map walled off from a crowd on its right, and the
+        // same half alone
+        let config = Config {
+            w: 33,
+            h: 16,
+            population: 0,
+            radius: 2,
+            ..small_config()
+        };
+        let rows: Vec<_> = (0..16).map(|_| format!("{:16}#{:16}", "", "")).collect();
+        let terrain = Terrain::try_from(rows).unwrap();
+        let mut walled = GameOfLife::new(config.with_offsets().with_terrain(&terrain));
+        let mut alone = GameOfLife::new(Config { w: 16, ..config }.with_offsets());
+        let mut rng = StdRng::seed_from_u64(4);
+        let prey = newborn(CellType::PREY, &alone.rules);
+        for y in 0..16 {
+            for x in 0..16 {
+                if rng.gen_bool(0.4) {
+                    walled.state.set(x, y, prey);
+                    alone.state.set(x, y, prey);
+                }
+            }
+            for x in 17..33 {
+                walled.state.set(x, y, prey);
+            }
+        }
+
+        for _ in 0..10 {
+            // Every cell rolls, so both halves draw the same random numbers
+            walled.state.activity = None;
+            alone.state.activity = None;
+            walled.step();
+            alone.step();
+            for (walled, alone) in walled.state.map.rows().zip(alone.state.map.rows()) {
+                assert!(walled[..16] == *alone);
+            }
+        }
+        assert!(alone.state.map.iter().any(|cell| cell.is_alive()));
+    }
+
+    #[test]
+    fn fertile_ground_multiplies_the_breeding_chance() {
+        // Exactly the reproduction threshold of prey around it
+        let mut neighbours = Neighbours::default();
+        for _ in 0..3 {
+            neighbours.add(sick(CellType::PREY, Health::Susceptible));
+        }
+        let genome = Genome::new(100, 0.5, 0, 5);
+        let cell = sick(CellType::PREY, Health::Susceptible).with_genome(genome);
+        let births = |fertility, ground| {
+            let game = GameOfLife::new(Config {
+                population: 0,
+                fertility,
+                ..small_config()
+            });
+            let site = Site {
+                ground,
+                resource: None,
+            };
+            let mut rng = StdRng::seed_from_u64(8);
+            (0..1000)
+                .filter(|_| {
+                    let inherit = |_, _: &mut StdRng| genome;
+                    let (_, transition) = game.apply_rules(
+                        cell,
+                        neighbours,
+                        Hunt::None,
+                        site,
+                        inherit,
+                        0.0,
+                        &mut rng,
+                    );
+                    transition == Some(Transition::Born)
+                })
+                .count()
+        };
+        assert_eq!(births(2.0, Ground::Fertile), 1000);
+        assert_eq!(births(0.0, Ground::Fertile), 0);
+        let open = births(2.0, Ground::Open);
+        assert!((400..600).contains(&open), "{open} births");
+    }
+
+    #[test]
+    fn stepping_back_and_forward_retraces_the_run() {
+        // The oldest snapshot kept is the one of generation 0, 20 and none
+        for (history, oldest) in [(50, 0), (5, 20), (0, 25)] {
+            let mut game = GameOfLife::new(Config {
+                history,
+                ..small_config()
+            });
+            let maps = run(&mut game, 25);
+            for generation in (0..25).rev() {
+                game.step_back();
+                assert_eq!(game.generation(), generation.max(oldest));
+                assert!(
+                    game.state.map == maps[game.generation()],
+                    "history {history}"
+                );
+            }
+            for map in &maps[oldest + 1..] {
+                game.step();
+                assert!(game.state.map == *map, "history {history}");
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_a_snapshot_every_interval() {
+        let mut game = GameOfLife::new(small_config());
+        run(&mut game, 3 * history::INTERVAL);
+        assert_eq!(game.history.len(), 3);
+
+        // Redoing doesn't snapshot the steps in between again
+        for _ in 0..history::INTERVAL + 5 {
+            game.step_back();
+        }
+        for _ in 0..history::INTERVAL + 5 {
+            game.step();
+        }
+        assert_eq!(game.generation(), 3 * history::INTERVAL);
+        assert_eq!(game.history.len(), 3);
+    }
+}
@@ -0,0 +1,76 @@
+use std::ops::{Index, IndexMut};
+
+// Row-major, contiguous 2D storage
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Grid<T> {
+    w: usize,
+    h: usize,
+    cells: Vec<T>,
+}
+
+impl<T: Clone> Grid<T> {
+    pub fn new(w: usize, h: usize, value: T) -> Self {
+        Self {
+            w,
+            h,
+            cells: vec![value; w * h],
+        }
+    }
+
+    pub fn fill(&mut self, value: T) {
+        self.cells.fill(value);
+    }
+}
+
+impl<T> Grid<T> {
+    pub fn width(&self) -> usize {
+        self.w
+    }
+
+    pub fn height(&self) -> usize {
+        self.h
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<&T> {
+        if x < self.w && y < self.h {
+            Some(&self.cells[y * self.w + x])
+        } else {
+            None
+        }
+    }
+
+    pub fn rows(&self) -> std::slice::ChunksExact<'_, T> {
+        self.cells.chunks_exact(self.w.max(1))
+    }
+
+    pub fn rows_mut(&mut self) -> std::slice::ChunksExactMut<'_, T> {
+        self.cells.chunks_exact_mut(self.w.max(1))
+    }
+
+    #[cfg(feature = "parallel")]
+    pub fn par_rows_mut(&mut self) -> rayon::slice::ChunksExactMut<'_, T>
+    where
+        T: Send,
+    {
+        use rayon::slice::ParallelSliceMut;
+        self.cells.par_chunks_exact_mut(self.w.max(1))
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.cells.iter()
+    }
+}
+
+impl<T> Index<(usize, usize)> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, (x, y): (usize, usize)) -> &T {
+        &self.cells[y * self.w + x]
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Grid<T> {
+    fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut T {
+        &mut self.cells[y * self.w + x]
+    }
+}
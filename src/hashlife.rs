@@ -0,0 +1,555 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::rule::LifeRule;
+use anyhow::{bail, Context, Result};
+
+pub type NodeId = u32;
+
+const DEAD: NodeId = 0;
+const ALIVE: NodeId = 1;
+
+// Results kept before the cache is cleared, roughly 100 MB of them
+const MAX_RESULTS: usize = 1 << 22;
+
+// Deepest node a macrocell file may have. Coordinates are i64, and the root
+// still has to grow a few levels around a pattern as it runs.
+const MAX_LEVEL: usize = 56;
+
+// A canonical quadtree node. Level 0 nodes are single cells, a level `k` node
+// is a 2^k square made of four level `k - 1` children in nw, ne, sw, se order.
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    level: u8,
+    children: [NodeId; 4],
+    population: u64,
+}
+
+// HashLife universe for a single two-state rule. Identical squares share one
+// node and the future of every node is memoized, so regular patterns can be
+// advanced by huge powers of two at once. Nodes are never freed, which keeps
+// old roots (e.g. in the history) valid, so memory grows with the number of
+// distinct squares a run has seen. The memoized results are only a cache and
+// are dropped once there are `MAX_RESULTS` of them.
+//
+// B0 rules aren't supported: an empty square is assumed to stay empty, while
+// under B0 all of the empty plane would be born at once.
+//
+// The root of level `k` covers [-2^(k-1), 2^(k-1)) on both axes, so world
+// coordinates stay put while the root grows and shrinks around the origin.
+pub struct Universe {
+    rule: LifeRule,
+    nodes: Vec<Node>,
+    lookup: HashMap<[NodeId; 4], NodeId>,
+    empty: Vec<NodeId>,
+    results: HashMap<(NodeId, u8), NodeId>,
+}
+
+impl Universe {
+    pub fn new(rule: LifeRule) -> Self {
+        debug_assert!(!rule.births(0), "HashLife can't run B0 rules");
+        let leaf = |population| Node {
+            level: 0,
+            children: [DEAD; 4],
+            population,
+        };
+        Self {
+            rule,
+            nodes: vec![leaf(0), leaf(1)],
+            lookup: HashMap::new(),
+            empty: vec![DEAD],
+            results: HashMap::new(),
+        }
+    }
+
+    pub fn rule(&self) -> LifeRule {
+        self.rule
+    }
+
+    pub fn nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn empty_root(&mut self) -> NodeId {
+        self.empty(3)
+    }
+
+    pub fn population(&self, node: NodeId) -> u64 {
+        self.nodes[node as usize].population
+    }
+
+    // Advances `root` by 2^`j` generations
+    pub fn advance(&mut self, root: NodeId, j: u8) -> NodeId {
+        if self.results.len() > MAX_RESULTS {
+            self.results.clear();
+        }
+        let mut root = root;
+        // Everything that can happen in 2^j generations must stay inside the
+        // centre half that `successor` returns
+        while self.level(root) < j + 3 || !self.is_padded(root) {
+            root = self.centre(root);
+        }
+        self.successor(root, j)
+    }
+
+    pub fn get(&self, root: NodeId, x: i64, y: i64) -> bool {
+        let half = 1i64 << (self.level(root) - 1);
+        if x < -half || y < -half || x >= half || y >= half {
+            return false;
+        }
+        let mut node = root;
+        let (mut x, mut y) = (x + half, y + half);
+        while self.level(node) > 0 {
+            let half = 1i64 << (self.level(node) - 1);
+            let quadrant = (x >= half) as usize + 2 * (y >= half) as usize;
+            node = self.children(node)[quadrant];
+            x %= half;
+            y %= half;
+        }
+        node == ALIVE
+    }
+
+    pub fn set(&mut self, root: NodeId, x: i64, y: i64, alive: bool) -> NodeId {
+        let mut root = root;
+        loop {
+            let half = 1i64 << (self.level(root) - 1);
+            if x >= -half && y >= -half && x < half && y < half {
+                return self.set_local(root, x + half, y + half, alive);
+            }
+            root = self.centre(root);
+        }
+    }
+
+    // Loads an RLE (.rle) or Golly macrocell (.mc) pattern, centred on the
+    // origin. A rule in the file is used unless `rule` is given.
+    pub fn load(path: &Path, rule: Option<LifeRule>) -> Result<(Self, NodeId)> {
+        let text =
+            fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        if text.starts_with("[M2]") {
+            Universe::load_macrocell(&text, rule)
+        } else {
+            Universe::load_rle(&text, rule)
+        }
+    }
+
+    fn load_rle(text: &str, rule: Option<LifeRule>) -> Result<(Self, NodeId)> {
+        let mut lines = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        let header = lines.next().context("RLE pattern is empty")?;
+
+        let mut width = 0i64;
+        let mut height = 0i64;
+        let mut file_rule = None;
+        for field in header.split(',') {
+            let Some((key, value)) = field.split_once('=') else {
+                bail!("invalid RLE header '{header}'");
+            };
+            match key.trim() {
+                "x" => width = value.trim().parse().context("invalid RLE width")?,
+                "y" => height = value.trim().parse().context("invalid RLE height")?,
+                "rule" => file_rule = Some(value.trim().parse()?),
+                _ => {}
+            }
+        }
+
+        let mut universe = Universe::new(supported(rule.or(file_rule))?);
+        let mut root = universe.empty_root();
+        let (mut x, mut y) = (0i64, 0i64);
+        let mut run = 0i64;
+        'body: for line in lines {
+            for c in line.chars() {
+                match c {
+                    '0'..='9' => run = run * 10 + c.to_digit(10).unwrap_or(0) as i64,
+                    '!' => break 'body,
+                    '$' => {
+                        y += run.max(1);
+                        x = 0;
+                        run = 0;
+                    }
+                    'b' | '.' => {
+                        x += run.max(1);
+                        run = 0;
+                    }
+                    c if c.is_ascii_alphabetic() => {
+                        for _ in 0..run.max(1) {
+                            root = universe.set(root, x - width / 2, y - height / 2, true);
+                            x += 1;
+                        }
+                        run = 0;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok((universe, root))
+    }
+
+    fn load_macrocell(text: &str, rule: Option<LifeRule>) -> Result<(Self, NodeId)> {
+        let file_rule = text
+            .lines()
+            .find_map(|line| line.strip_prefix("#R"))
+            .map(|rule| rule.trim().parse())
+            .transpose()?;
+        let mut universe = Universe::new(supported(rule.or(file_rule))?);
+
+        // Nodes are numbered from 1 in file order, 0 is an empty node
+        let mut ids = vec![DEAD];
+        for line in text.lines().skip(1) {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let node = if line.starts_with(['.', '*', '$']) {
+                universe.leaf_8x8(line)
+            } else {
+                let fields = line
+                    .split_whitespace()
+                    .map(|f| f.parse::<usize>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .with_context(|| format!("invalid macrocell line '{line}'"))?;
+                let [level, children @ ..] = fields.as_slice() else {
+                    bail!("invalid macrocell line '{line}'");
+                };
+                if children.len() != 4 || *level < 4 {
+                    bail!("invalid macrocell line '{line}'");
+                }
+                if *level > MAX_LEVEL {
+                    bail!("macrocell level {level} is deeper than the supported {MAX_LEVEL}");
+                }
+                let below = *level as u8 - 1;
+                let mut quadrants = [DEAD; 4];
+                for (quadrant, &child) in quadrants.iter_mut().zip(children) {
+                    *quadrant = match child {
+                        0 => universe.empty(below),
+                        n => *ids.get(n).context("macrocell refers to a later node")?,
+                    };
+                    if universe.level(*quadrant) != below {
+                        bail!("macrocell node '{line}' has children of another level than {below}");
+                    }
+                }
+                universe.join(quadrants)
+            };
+            ids.push(node);
+        }
+        let root = *ids.last().context("macrocell pattern is empty")?;
+        let root = if root == DEAD {
+            universe.empty_root()
+        } else {
+            root
+        };
+        Ok((universe, root))
+    }
+
+    fn leaf_8x8(&mut self, line: &str) -> NodeId {
+        let mut cells = [[DEAD; 8]; 8];
+        let (mut x, mut y) = (0, 0);
+        for c in line.chars() {
+            match c {
+                '$' => {
+                    x = 0;
+                    y += 1;
+                }
+                '*' if x < 8 && y < 8 => {
+                    cells[y][x] = ALIVE;
+                    x += 1;
+                }
+                _ => x += 1,
+            }
+        }
+        self.build(&cells, 0, 0, 8)
+    }
+
+    fn build(&mut self, cells: &[[NodeId; 8]; 8], x: usize, y: usize, size: usize) -> NodeId {
+        if size == 1 {
+            return cells[y][x];
+        }
+        let half = size / 2;
+        let nw = self.build(cells, x, y, half);
+        let ne = self.build(cells, x + half, y, half);
+        let sw = self.build(cells, x, y + half, half);
+        let se = self.build(cells, x + half, y + half, half);
+        self.join([nw, ne, sw, se])
+    }
+
+    fn level(&self, node: NodeId) -> u8 {
+        self.nodes[node as usize].level
+    }
+
+    fn children(&self, node: NodeId) -> [NodeId; 4] {
+        self.nodes[node as usize].children
+    }
+
+    fn join(&mut self, children: [NodeId; 4]) -> NodeId {
+        if let Some(&node) = self.lookup.get(&children) {
+            return node;
+        }
+        let node = Node {
+            level: self.level(children[0]) + 1,
+            children,
+            population: children.iter().map(|&c| self.population(c)).sum(),
+        };
+        let id = self.nodes.len() as NodeId;
+        self.nodes.push(node);
+        self.lookup.insert(children, id);
+        id
+    }
+
+    fn empty(&mut self, level: u8) -> NodeId {
+        while self.empty.len() <= level as usize {
+            let below = *self.empty.last().unwrap_or(&DEAD);
+            let node = self.join([below; 4]);
+            self.empty.push(node);
+        }
+        self.empty[level as usize]
+    }
+
+    // The same square in the middle of one twice its size
+    fn centre(&mut self, node: NodeId) -> NodeId {
+        let [nw, ne, sw, se] = self.children(node);
+        let e = self.empty(self.level(node) - 1);
+        let nw = self.join([e, e, e, nw]);
+        let ne = self.join([e, e, ne, e]);
+        let sw = self.join([e, sw, e, e]);
+        let se = self.join([se, e, e, e]);
+        self.join([nw, ne, sw, se])
+    }
+
+    // Whether all live cells are inside the centre quarter
+    fn is_padded(&self, node: NodeId) -> bool {
+        let [nw, ne, sw, se] = self.children(node);
+        let inner = |quadrant: NodeId, corner: usize| {
+            let middle = self.children(quadrant)[corner];
+            self.children(middle)[corner]
+        };
+        self.population(nw) == self.population(inner(nw, 3))
+            && self.population(ne) == self.population(inner(ne, 2))
+            && self.population(sw) == self.population(inner(sw, 1))
+            && self.population(se) == self.population(inner(se, 0))
+    }
+
+    fn set_local(&mut self, node: NodeId, x: i64, y: i64, alive: bool) -> NodeId {
+        if self.level(node) == 0 {
+            return if alive { ALIVE } else { DEAD };
+        }
+        let half = 1i64 << (self.level(node) - 1);
+        let quadrant = (x >= half) as usize + 2 * (y >= half) as usize;
+        let mut children = self.children(node);
+        children[quadrant] = self.set_local(children[quadrant], x % half, y % half, alive);
+        self.join(children)
+    }
+
+    // The centre half of a level `k` node, 2^min(j, k - 2) generations later
+    fn successor(&mut self, node: NodeId, j: u8) -> NodeId {
+        let level = self.level(node);
+        if self.population(node) == 0 {
+            return self.empty(level - 1);
+        }
+        if level == 2 {
+            return self.step_4x4(node);
+        }
+        let j = j.min(level - 2);
+        if let Some(&result) = self.results.get(&(node, j)) {
+            return result;
+        }
+
+        let [a, b, c, d] = self.children(node);
+        let [aa, ab, ac, ad] = self.children(a);
+        let [ba, bb, bc, bd] = self.children(b);
+        let [ca, cb, cc, cd] = self.children(c);
+        let [da, db, dc, dd] = self.children(d);
+
+        // Nine overlapping squares half the size, each advanced
+        let mut c1 = [0; 9];
+        for (result, quadrants) in c1.iter_mut().zip([
+            [aa, ab, ac, ad],
+            [ab, ba, ad, bc],
+            [ba, bb, bc, bd],
+            [ac, ad, ca, cb],
+            [ad, bc, cb, da],
+            [bc, bd, da, db],
+            [ca, cb, cc, cd],
+            [cb, da, cd, dc],
+            [da, db, dc, dd],
+        ]) {
+            let square = self.join(quadrants);
+            *result = self.successor(square, j);
+        }
+        let [n1, n2, n3, n4, n5, n6, n7, n8, n9] = c1;
+
+        let result = if j < level - 2 {
+            // Only the inner parts, without advancing any further
+            let mut inner = |quadrants: [NodeId; 4]| {
+                let [q1, q2, q3, q4] = quadrants;
+                let parts = [
+                    self.children(q1)[3],
+                    self.children(q2)[2],
+                    self.children(q3)[1],
+                    self.children(q4)[0],
+                ];
+                self.join(parts)
+            };
+            let nw = inner([n1, n2, n4, n5]);
+            let ne = inner([n2, n3, n5, n6]);
+            let sw = inner([n4, n5, n7, n8]);
+            let se = inner([n5, n6, n8, n9]);
+            self.join([nw, ne, sw, se])
+        } else {
+            let mut advance = |quadrants: [NodeId; 4]| {
+                let square = self.join(quadrants);
+                self.successor(square, j)
+            };
+            let nw = advance([n1, n2, n4, n5]);
+            let ne = advance([n2, n3, n5, n6]);
+            let sw = advance([n4, n5, n7, n8]);
+            let se = advance([n5, n6, n8, n9]);
+            self.join([nw, ne, sw, se])
+        };
+        self.results.insert((node, j), result);
+        result
+    }
+
+    // Brute force base case: the centre 2x2 of a 4x4 node one generation later
+    fn step_4x4(&mut self, node: NodeId) -> NodeId {
+        let mut cells = [[false; 4]; 4];
+        for (y, row) in cells.iter_mut().enumerate() {
+            for (x, cell) in row.iter_mut().enumerate() {
+                let quadrant = self.children(node)[x / 2 + 2 * (y / 2)];
+                *cell = self.children(quadrant)[x % 2 + 2 * (y % 2)] == ALIVE;
+            }
+        }
+
+        let mut next = [DEAD; 4];
+        for (i, (x, y)) in [(1, 1), (2, 1), (1, 2), (2, 2)].into_iter().enumerate() {
+            let neighbours = cells[y - 1..=y + 1]
+                .iter()
+                .flat_map(|row| &row[x - 1..=x + 1])
+                .filter(|&&alive| alive)
+                .count()
+                - cells[y][x] as usize;
+            let alive = if cells[y][x] {
+                self.rule.survives(neighbours)
+            } else {
+                self.rule.births(neighbours)
+            };
+            if alive {
+                next[i] = ALIVE;
+            }
+        }
+        self.join(next)
+    }
+}
+
+// The rule a pattern runs with, Conway's unless it names one
+fn supported(rule: Option<LifeRule>) -> Result<LifeRule> {
+    let rule = rule.unwrap_or(LifeRule::CONWAY);
+    if rule.births(0) {
+        bail!("HashLife can't run B0 rules");
+    }
+    Ok(rule)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitgrid::BitGrid;
+
+    const GLIDER: &str = "#N Glider\nx = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n";
+    const R_PENTOMINO: &str = "x = 3, y = 3\nb2o$2o$bo!\n";
+
+    fn alive(universe: &Universe, root: NodeId, size: i64) -> Vec<(i64, i64)> {
+        let half = size / 2;
+        (-half..half)
+            .flat_map(|y| (-half..half).map(move |x| (x, y)))
+            .filter(|&(x, y)| universe.get(root, x, y))
+            .collect()
+    }
+
+    #[test]
+    fn matches_the_bit_grid() {
+        const SIZE: usize = 256;
+        for pattern in [GLIDER, R_PENTOMINO] {
+            let (mut universe, mut root) = Universe::load_rle(pattern, None).unwrap();
+            let mut grid = BitGrid::new(SIZE, SIZE);
+            let offset = (SIZE / 2) as i64;
+            for (x, y) in alive(&universe, root, SIZE as i64) {
+                grid.set((x + offset) as usize, (y + offset) as usize, true);
+            }
+            let mut next = BitGrid::default();
+
+            // One generation at a time, then a jump as far as all of them
+            let start = root;
+            for _ in 0..64 {
+                grid.step(LifeRule::CONWAY, &mut next);
+                std::mem::swap(&mut grid, &mut next);
+                root = universe.advance(root, 0);
+                assert_eq!(universe.population(root), grid.population() as u64);
+                for (x, y) in alive(&universe, root, SIZE as i64) {
+                    assert!(grid.get((x + offset) as usize, (y + offset) as usize));
+                }
+            }
+            let jumped = universe.advance(start, 6);
+            assert_eq!(
+                alive(&universe, jumped, SIZE as i64),
+                alive(&universe, root, SIZE as i64)
+            );
+        }
+    }
+
+    #[test]
+    fn loads_rle_centred_on_the_origin() {
+        let (universe, root) = Universe::load_rle(GLIDER, None).unwrap();
+        assert_eq!(
+            alive(&universe, root, 8),
+            [(0, -1), (1, 0), (-1, 1), (0, 1), (1, 1)]
+        );
+
+        let rule = "B36/S23".parse().unwrap();
+        let (universe, _) = Universe::load_rle(GLIDER, Some(rule)).unwrap();
+        assert_eq!(universe.rule(), rule);
+        let (universe, _) = Universe::load_rle(R_PENTOMINO, None).unwrap();
+        assert_eq!(universe.rule(), LifeRule::CONWAY);
+    }
+
+    #[test]
+    fn macrocell_matches_rle() {
+        // The glider in the top left of the south east 8x8 quadrant
+        let text = "[M2] (golly 4.0)\n#R B3/S23\n.*$..*$***$\n4 0 0 0 1\n";
+        let (universe, root) = Universe::load_macrocell(text, None).unwrap();
+        let (rle, rle_root) = Universe::load_rle(GLIDER, None).unwrap();
+        let shifted: Vec<_> = alive(&rle, rle_root, 16)
+            .into_iter()
+            .map(|(x, y)| (x + 1, y + 1))
+            .collect();
+        assert_eq!(alive(&universe, root, 16), shifted);
+
+        let later = "[M2]\n4 0 0 0 2\n.*$\n";
+        assert!(Universe::load_macrocell(later, None).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_macrocells() {
+        // A level 5 node of a leaf, whose level is 3
+        let skipped = "[M2]\n.*$\n5 0 0 0 1\n";
+        assert!(Universe::load_macrocell(skipped, None).is_err());
+        // A level 5 node of a level 4 and a leaf
+        let mixed = "[M2]\n.*$\n4 0 0 0 1\n5 2 1 0 0\n";
+        assert!(Universe::load_macrocell(mixed, None).is_err());
+        for level in [64, 200, 300] {
+            let deep = format!("[M2]\n{level} 0 0 0 0\n");
+            assert!(
+                Universe::load_macrocell(&deep, None).is_err(),
+                "level {level}"
+            );
+        }
+        let nested = "[M2]\n.*$\n4 0 0 0 1\n5 2 0 0 2\n";
+        assert!(Universe::load_macrocell(nested, None).is_ok());
+    }
+
+    #[test]
+    fn rejects_b0_rules() {
+        assert!(Universe::load_rle("x = 1, y = 1, rule = B03/S23\no!", None).is_err());
+        let b0 = "B0/S8".parse().ok();
+        assert!(Universe::load_macrocell("[M2]\n.*$\n4 0 0 0 1\n", b0).is_err());
+    }
+}
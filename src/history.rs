@@ -0,0 +1,165 @@
+use std::collections::VecDeque;
+
+// Steps between two snapshots of a run. Stepping back re-simulates from the
+// nearest one, so a snapshot every step would only cost memory and time.
+pub const INTERVAL: usize = 10;
+
+// Bounded undo/redo stacks of past simulation snapshots. Once `capacity` is
+// reached the oldest past and the furthest future entries are dropped.
+#[derive(Debug, Clone)]
+pub struct History<T> {
+    past: VecDeque<T>,
+    future: VecDeque<T>,
+    capacity: usize,
+}
+
+impl<T> History<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            past: VecDeque::with_capacity(capacity),
+            future: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    // Remembers a snapshot of the past, newer than the ones before it
+    pub fn record(&mut self, snapshot: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.past.len() == self.capacity {
+            self.past.pop_front();
+        }
+        self.past.push_back(snapshot);
+    }
+
+    pub fn latest(&self) -> Option<&T> {
+        self.past.back()
+    }
+
+    // Goes back to the latest snapshot that isn't `newer` than the target,
+    // dropping the ones that are and keeping `current` so it can be redone.
+    // Without such a snapshot nothing changes.
+    pub fn undo(&mut self, current: T, newer: impl Fn(&T) -> bool) -> Option<T> {
+        let older = self.past.iter().rposition(|snapshot| !newer(snapshot))?;
+        self.past.truncate(older + 1);
+        if self.future.len() == self.capacity {
+            self.future.pop_front();
+        }
+        self.future.push_back(current);
+        self.past.pop_back()
+    }
+
+    pub fn redo(&mut self) -> Option<T> {
+        self.future.pop_back()
+    }
+
+    // Drops the redo stack when the timeline branches off
+    pub fn branch(&mut self) {
+        self.future.clear();
+    }
+
+    pub fn is_rewound(&self) -> bool {
+        !self.future.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.past.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.past.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.past.clear();
+        self.future.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_goes_back_to_the_latest_older_snapshot() {
+        let mut history = History::new(4);
+        for generation in [0, 10, 20] {
+            history.record(generation);
+        }
+        assert_eq!(history.undo(25, |&g| g > 24), Some(20));
+        assert_eq!(history.undo(24, |&g| g > 23), Some(10));
+        assert_eq!(history.len(), 1);
+        assert!(history.is_rewound());
+    }
+
+    #[test]
+    fn redo_returns_the_undone_states_in_order() {
+        let mut history = History::new(4);
+        history.record(0);
+        history.record(1);
+        assert_eq!(history.undo(2, |&g| g > 1), Some(1));
+        assert_eq!(history.undo(1, |&g| g > 0), Some(0));
+        assert_eq!(history.redo(), Some(1));
+        assert_eq!(history.redo(), Some(2));
+        assert_eq!(history.redo(), None);
+    }
+
+    #[test]
+    fn stops_at_the_oldest_snapshot() {
+        let mut history = History::new(4);
+        history.record(10);
+        assert_eq!(history.undo(12, |&g| g > 9), None);
+        assert_eq!(history.len(), 1);
+        assert!(!history.is_rewound());
+    }
+
+    #[test]
+    fn drops_the_oldest_snapshots_beyond_capacity() {
+        let mut history = History::new(2);
+        for generation in 0..5 {
+            history.record(generation);
+        }
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.undo(5, |_| false), Some(4));
+        assert_eq!(history.undo(4, |_| false), Some(3));
+        assert_eq!(history.undo(3, |_| false), None);
+    }
+
+    #[test]
+    fn drops_the_furthest_future_beyond_capacity() {
+        let mut history = History::new(2);
+        history.record(0);
+        history.record(1);
+        assert_eq!(history.undo(12, |_| false), Some(1));
+        history.record(1);
+        history.record(2);
+        assert_eq!(history.undo(11, |_| false), Some(2));
+        assert_eq!(history.undo(10, |_| false), Some(1));
+        assert_eq!(history.redo(), Some(10));
+        assert_eq!(history.redo(), Some(11));
+        assert_eq!(history.redo(), None);
+    }
+
+    #[test]
+    fn keeps_nothing_at_capacity_zero() {
+        let mut history = History::new(0);
+        history.record(0);
+        assert!(history.is_empty());
+        for generation in 1..100 {
+            assert_eq!(history.undo(generation, |_| false), None);
+        }
+        assert!(!history.is_rewound());
+        assert_eq!(history.redo(), None);
+    }
+
+    #[test]
+    fn branching_drops_the_redo_stack() {
+        let mut history = History::new(2);
+        history.record(0);
+        history.undo(1, |_| false);
+        history.branch();
+        assert!(!history.is_rewound());
+        assert_eq!(history.redo(), None);
+    }
+}
@@ -0,0 +1,27 @@
+pub mod activity;
+pub mod bench;
+pub mod bitgrid;
+pub mod cast;
+pub mod cell;
+pub mod config;
+pub mod controls;
+pub mod export;
+pub mod game;
+pub mod grid;
+pub mod hashlife;
+pub mod history;
+pub mod metrics;
+pub mod movement;
+pub mod neighbours;
+pub mod predation;
+pub mod replay;
+pub mod resources;
+pub mod rule;
+pub mod schedule;
+pub mod species;
+pub mod stats;
+pub mod terrain;
+pub mod world;
+
+#[cfg(test)]
+mod testing;
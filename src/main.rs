@@ -1,45 +1,91 @@
-mod cell;
-mod config;
-mod game;
+use std::path::{Path, PathBuf};
 
-use anyhow::Result;
-use clap::Parser;
-use config::Config;
-use game::GameOfLife;
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use gameoflife::bench::{self, Backend, Report};
+use gameoflife::cast::{self, CastRecording};
+use gameoflife::cell::Genome;
+use gameoflife::config::{Config, Follow, FrameSkip, Shape};
+use gameoflife::export::Export;
+use gameoflife::game::GameOfLife;
+use gameoflife::hashlife::Universe;
+use gameoflife::metrics::MetricsLog;
+use gameoflife::replay::{EventLog, EventLogWriter};
+use gameoflife::rule::LifeRule;
+use gameoflife::schedule::{self, Schedule};
+use gameoflife::species::{self, FoodWeb};
+use gameoflife::terrain::Terrain;
 
 #[derive(Parser)]
 #[command(version = "1.0")]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(long)]
     width: Option<usize>,
     #[arg(long)]
     height: Option<usize>,
     #[arg(short, long)]
     population: Option<usize>,
-    #[arg(short, long)]
+    #[arg(
+        short,
+        long,
+        value_parser = parse_fps,
+        help = "Frames per second, or 'max' to run uncapped"
+    )]
     fps: Option<usize>,
+    #[arg(
+        long,
+        default_value = "1",
+        help = "Steps simulated between rendered frames, or 'auto' to fill the frame time"
+    )]
+    gens_per_frame: FrameSkip,
     #[arg(
         short,
         long,
         help = "Set the radius that is checked to determine if a cell lives on or is born"
     )]
     radius: Option<usize>,
+    #[arg(
+        long,
+        value_enum,
+        help = "Shape of the neighbourhood within the radius"
+    )]
+    shape: Option<Shape>,
     #[arg(short = 'c', long, help = "cluster size")]
     size: Option<usize>,
     #[arg(short, long, help = "cluster density")]
     density: Option<f64>,
     #[arg(short, long, default_value_t = false)]
     info: bool,
+    #[arg(
+        long,
+        help = "Past generations that can be stepped back to, a snapshot of every tenth is kept"
+    )]
+    history: Option<usize>,
+    #[cfg(feature = "parallel")]
+    #[arg(
+        long,
+        help = "Worker threads for the simulation step, 0 uses every core"
+    )]
+    threads: Option<usize>,
 
     #[arg(short = 'x', long)]
     reproduction: Option<usize>,
+    #[arg(
+        long,
+        value_parser = parse_chance,
+        help = "Chance of a live cell breeding in a generation"
+    )]
+    reproduction_chance: Option<f64>,
     #[arg(short, long)]
     overpopulation: Option<usize>,
     #[arg(short, long)]
     underpopulation: Option<usize>,
     #[arg(short, long)]
     survivability: Option<usize>,
-    #[arg(short, long)]
+    #[arg(short, long, value_parser = parse_age)]
     age: Option<usize>,
     #[arg(short, long)]
     mutation: Option<f64>,
@@ -49,21 +95,390 @@ struct Cli {
     aging: Option<usize>,
     #[arg(short = 'P', long)]
     predetor_rate: Option<f64>,
+    #[arg(long, help = "Energy a predator burns every generation")]
+    energy_loss: Option<usize>,
+    #[arg(
+        long,
+        help = "Energy a predator gains in a generation with prey around it"
+    )]
+    energy_gain: Option<usize>,
+    #[arg(
+        long,
+        help = "Energy a predator needs to breed, breeding costs it --birth-energy"
+    )]
+    breed_energy: Option<usize>,
+    #[arg(long, help = "Energy newborn predators start with")]
+    birth_energy: Option<usize>,
+    #[arg(
+        long,
+        help = "Predators with enough energy to breed put their newborn into the cell of the prey they eat"
+    )]
+    offspring: bool,
+    #[arg(
+        long,
+        value_parser = parse_chance,
+        help = "Chance that a caught prey gets away anyway"
+    )]
+    escape: Option<f64>,
+    #[arg(
+        long,
+        help = "After each step predators move toward prey and prey flee from predators"
+    )]
+    movement: bool,
+    #[arg(
+        long,
+        value_parser = parse_chance,
+        help = "Chance of each trait of a newborn's inherited genome changing"
+    )]
+    gene_mutation: Option<f64>,
+    #[arg(
+        long,
+        help = "Prey graze a resource that regrows and spreads over the map, shown under empty cells"
+    )]
+    resources: bool,
+    #[arg(
+        long,
+        value_parser = parse_share,
+        help = "With --resources, what a prey eats each generation and needs under it to survive or be born"
+    )]
+    grazing: Option<f64>,
+    #[arg(
+        long,
+        value_parser = parse_share,
+        help = "With --resources, share of the difference to the cells around that spreads each generation"
+    )]
+    diffusion: Option<f64>,
+    #[arg(
+        long,
+        value_parser = parse_share,
+        help = "With --resources, share of what is missing to the full level that grows back each generation"
+    )]
+    regrowth: Option<f64>,
+    #[arg(
+        long,
+        value_parser = parse_factor,
+        help = "With --terrain, how many times more likely prey breed on fertile ground"
+    )]
+    fertility: Option<f64>,
+    #[arg(
+        long,
+        value_parser = parse_chance,
+        help = "Chance of catching the epidemic from each infected neighbour per generation, 0 turns it off"
+    )]
+    infection: Option<f64>,
+    #[arg(
+        long,
+        value_parser = parse_chance,
+        help = "With --infection, factor on it for infected neighbours of another species"
+    )]
+    cross_infection: Option<f64>,
+    #[arg(
+        long,
+        value_parser = parse_chance,
+        help = "With --infection, chance per generation of an infected cell recovering for good"
+    )]
+    recovery: Option<f64>,
+    #[arg(
+        long,
+        value_parser = parse_chance,
+        help = "With --infection, chance per generation of an infected cell dying of it"
+    )]
+    virulence: Option<f64>,
+    #[arg(
+        long,
+        help = "With --infection, extra generations an infected cell ages each generation"
+    )]
+    sick_aging: Option<usize>,
+    #[arg(
+        long,
+        value_parser = parse_chance,
+        help = "With --infection, share of the first cells that start infected"
+    )]
+    infected: Option<f64>,
+    #[arg(
+        long,
+        help = "Text or PNG map of walls, barren, fertile and refuge ground, stretched over the grid"
+    )]
+    terrain: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "JSON file of seasons, parameter changes and events such as meteors at given generations"
+    )]
+    schedule: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "JSON file with the species of the run and who eats whom, instead of prey and predators"
+    )]
+    food_web: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Run a single species Life-like rule in B/S notation instead, e.g. B3/S23"
+    )]
+    rule: Option<LifeRule>,
+    #[arg(
+        long,
+        help = "Run the rule on an unbounded HashLife universe (defaults to B3/S23)"
+    )]
+    hashlife: bool,
+    #[arg(
+        long,
+        default_value_t = 0,
+        value_parser = clap::value_parser!(u8).range(0..=48),
+        help = "With --hashlife, advance 2^N generations per frame"
+    )]
+    step_exp: u8,
+    #[arg(
+        long,
+        help = "Load an RLE or macrocell pattern into the HashLife universe"
+    )]
+    pattern: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Simulate an unbounded plane instead of a grid the size of the screen"
+    )]
+    infinite: bool,
+    #[arg(
+        long,
+        default_value = "centroid",
+        help = "With --infinite, keep 'centroid' of the population or a point X,Y in view"
+    )]
+    follow: Follow,
+
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["N", "PATTERN"],
+        help = "Write every Nth frame as a PNG, e.g. --png-every 10 out/frame_%06d.png"
+    )]
+    png_every: Vec<String>,
+    #[arg(long, help = "Record an animated GIF to this file")]
+    gif: Option<PathBuf>,
+    #[arg(
+        long,
+        default_value_t = 500,
+        help = "Number of frames to record into the GIF"
+    )]
+    frames: usize,
+    #[arg(long, default_value_t = 4, help = "Pixels per cell in exported images")]
+    scale: usize,
+    #[arg(long, help = "Record the terminal output to an asciinema v2 cast file")]
+    record: Option<PathBuf>,
+    #[arg(long, help = "Seed for the random number generator, random if not set")]
+    seed: Option<u64>,
+    #[arg(
+        long,
+        help = "Write the seed, config and interactive events to a log that `replay` can re-simulate"
+    )]
+    log: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Run without the terminal, only writing exports, logs and metrics"
+    )]
+    headless: bool,
+    #[arg(
+        long,
+        default_value_t = 1000,
+        help = "Generations to simulate with --headless"
+    )]
+    generations: usize,
+    #[arg(
+        long,
+        help = "Write frame rate and simulation/render timings per frame as JSON lines"
+    )]
+    metrics: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Play back a cast recorded with --record or re-simulate a --log")]
+    Replay {
+        file: PathBuf,
+        #[arg(long, default_value_t = 1.0, help = "Playback speed multiplier")]
+        speed: f64,
+    },
+    #[command(
+        about = "Measure generations per second across grid sizes, radii, densities and backends"
+    )]
+    Bench {
+        #[arg(long, default_value_t = 100, help = "Generations timed per case")]
+        generations: usize,
+        #[arg(long, value_enum, help = "Only run these backends, may be repeated")]
+        backend: Vec<Backend>,
+        #[arg(long, help = "Only run the smallest grid size")]
+        quick: bool,
+        #[arg(long, help = "Write the report as JSON")]
+        output: Option<PathBuf>,
+        #[arg(long, help = "Compare against a report written by an earlier run")]
+        baseline: Option<PathBuf>,
+        #[arg(
+            long,
+            default_value_t = 10.0,
+            help = "Slowdown in percent against the baseline that fails the run"
+        )]
+        threshold: f64,
+    },
+}
+
+// "max" runs uncapped, which the config stores as 0
+fn parse_fps(s: &str) -> Result<usize, String> {
+    if s.eq_ignore_ascii_case("max") {
+        return Ok(0);
+    }
+    match s.parse() {
+        Ok(0) => Err("use 'max' to run uncapped".to_string()),
+        Ok(fps) => Ok(fps),
+        Err(_) => Err(format!("expected a number or 'max', got '{s}'")),
+    }
+}
+
+// A probability, out of range ones would panic when rolled
+fn parse_chance(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(p) if (0.0..=1.0).contains(&p) => Ok(p),
+        Ok(_) => Err("expected a chance from 0 to 1".to_string()),
+        Err(_) => Err(format!("expected a number, got '{s}'")),
+    }
+}
+
+// A share of the resource capacity or of a difference in it, none to all of it
+fn parse_share(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(share) if (0.0..=1.0).contains(&share) => Ok(share),
+        Ok(_) => Err("expected a share from 0 to 1".to_string()),
+        Err(_) => Err(format!("expected a number, got '{s}'")),
+    }
+}
+
+// A multiplier on chances, which can't go below 0
+fn parse_factor(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(factor) if factor >= 0.0 && factor.is_finite() => Ok(factor),
+        Ok(_) => Err("expected a factor of 0 or more".to_string()),
+        Err(_) => Err(format!("expected a number, got '{s}'")),
+    }
+}
+
+// Genomes only have room for ages up to `Genome::MAX_AGE`
+fn parse_age(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(age @ 1..=Genome::MAX_AGE) => Ok(age),
+        Ok(_) => Err(format!("expected an age from 1 to {}", Genome::MAX_AGE)),
+        Err(_) => Err(format!("expected a number, got '{s}'")),
+    }
+}
+
+#[cfg(feature = "parallel")]
+fn threads(cli: &Cli) -> Result<usize> {
+    let threads = cli.threads.unwrap_or(0);
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()?;
+    Ok(rayon::current_num_threads())
+}
+
+#[cfg(not(feature = "parallel"))]
+fn threads(_cli: &Cli) -> Result<usize> {
+    Ok(1)
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
-    let config = Config::new(
+    if cli.pattern.is_some() && !cli.hashlife {
+        bail!("--pattern needs --hashlife");
+    }
+    if cli.headless && cli.record.is_some() {
+        bail!("--record captures the terminal, which --headless doesn't draw");
+    }
+    if cli.hashlife && cli.infinite {
+        bail!("--hashlife is already unbounded, drop --infinite");
+    }
+    if cli.hashlife
+        && (cli.radius.is_some_and(|r| r != 1) || cli.shape.unwrap_or_default() != Shape::Moore)
+    {
+        bail!("--hashlife only supports a Moore neighbourhood of radius 1");
+    }
+    if cli.hashlife && cli.rule.is_some_and(|rule| rule.births(0)) {
+        bail!("--hashlife can't run B0 rules, use them without it");
+    }
+    if cli.food_web.is_some() && (cli.rule.is_some() || cli.hashlife) {
+        bail!("--food-web can't be combined with a single species rule");
+    }
+    if cli.resources && (cli.rule.is_some() || cli.hashlife || cli.infinite) {
+        bail!("--resources needs the predator-prey rules on a bounded map");
+    }
+    if cli.terrain.is_some() && (cli.rule.is_some() || cli.hashlife || cli.infinite) {
+        bail!("--terrain needs the predator-prey rules on a bounded map");
+    }
+    if cli.infection.is_some_and(|p| p > 0.0) && (cli.rule.is_some() || cli.hashlife) {
+        bail!("--infection needs the predator-prey rules");
+    }
+    let web = match &cli.food_web {
+        Some(path) => &*Box::leak(Box::new(FoodWeb::load(path)?)),
+        None => species::default_web(),
+    };
+    let schedule = match &cli.schedule {
+        Some(path) => &*Box::leak(Box::new(Schedule::load(path)?)),
+        None => schedule::no_schedule(),
+    };
+    // B/S rules count the 8 surrounding cells unless told otherwise
+    let life = cli.rule.is_some() || cli.hashlife;
+    let rule = match cli.rule {
+        None if cli.hashlife => Some(LifeRule::CONWAY),
+        rule => rule,
+    };
+
+    if let Some(Command::Replay { file, speed }) = &cli.command {
+        if !(*speed > 0.0 && speed.is_finite()) {
+            bail!("--speed must be a positive number");
+        }
+        if !EventLog::sniff(file)? {
+            return cast::play(file, *speed);
+        }
+        let mut game = GameOfLife::replay(EventLog::load(file)?, *speed);
+        return game.run();
+    }
+
+    if let Some(Command::Bench {
+        generations,
+        backend,
+        quick,
+        output,
+        baseline,
+        threshold,
+    }) = &cli.command
+    {
+        let backends = if backend.is_empty() {
+            &Backend::ALL[..]
+        } else {
+            backend
+        };
+        return run_bench(
+            backends,
+            *quick,
+            *generations,
+            output.as_deref(),
+            baseline.as_deref(),
+            *threshold,
+        );
+    }
+
+    let mut config = Config::new(
         cli.width,
         cli.height,
         cli.population.unwrap_or(2000),
         cli.fps.unwrap_or(24),
-        cli.radius.unwrap_or(5),
+        cli.gens_per_frame,
+        cli.radius.unwrap_or(if life { 1 } else { 5 }),
+        cli.shape.unwrap_or_default(),
         cli.size.unwrap_or(50),
         cli.density.unwrap_or(0.7),
         cli.info,
+        cli.history.unwrap_or(50),
+        threads(&cli)?,
         cli.reproduction.unwrap_or(3),
+        cli.reproduction_chance.unwrap_or(0.1),
         cli.overpopulation.unwrap_or(4),
         cli.underpopulation.unwrap_or(1),
         cli.survivability.unwrap_or(2),
@@ -72,8 +487,107 @@ fn main() -> Result<()> {
         cli.resitence.unwrap_or(2),
         cli.aging.unwrap_or(1),
         cli.predetor_rate.unwrap_or(0.01),
+        cli.energy_loss.unwrap_or(1),
+        cli.energy_gain.unwrap_or(3),
+        cli.breed_energy.unwrap_or(12),
+        cli.birth_energy.unwrap_or(6),
+        cli.offspring,
+        cli.escape.unwrap_or(0.01),
+        cli.movement,
+        cli.gene_mutation.unwrap_or(0.05),
+        cli.resources,
+        cli.grazing.unwrap_or(0.2),
+        cli.diffusion.unwrap_or(0.1),
+        cli.regrowth.unwrap_or(0.05),
+        cli.fertility.unwrap_or(2.0),
+        cli.infection.unwrap_or(0.0),
+        cli.cross_infection.unwrap_or(0.1),
+        cli.recovery.unwrap_or(0.05),
+        cli.virulence.unwrap_or(0.01),
+        cli.sick_aging.unwrap_or(2),
+        cli.infected.unwrap_or(0.01),
+        web,
+        schedule,
+        cli.seed.unwrap_or_else(rand::random),
+        rule,
+        cli.hashlife,
+        cli.step_exp,
+        cli.infinite,
+        cli.follow,
     );
+    if let Some(path) = &cli.terrain {
+        config = config.with_terrain(&Terrain::load(path)?);
+    }
+
+    let mut export = Export::new(cli.scale);
+    if let [every, pattern] = cli.png_every.as_slice() {
+        let every = every
+            .parse()
+            .with_context(|| format!("invalid --png-every interval '{every}'"))?;
+        export = export.png_every(every, pattern.clone())?;
+    }
+    if let Some(path) = &cli.gif {
+        export = export.gif(path, cli.frames, config.w, config.h, config.fps)?;
+    }
 
-    let mut game = GameOfLife::new(config);
+    let mut game = GameOfLife::new(config).with_export(export);
+    if let Some(path) = &cli.pattern {
+        let (universe, root) = Universe::load(path, cli.rule)?;
+        game = game.with_universe(universe, root);
+    }
+    if let Some(path) = &cli.record {
+        game = game.with_recording(CastRecording::create(path, config.w, config.h)?);
+    }
+    if let Some(path) = &cli.log {
+        game = game.with_log(EventLogWriter::create(path, config)?);
+    }
+    if let Some(path) = &cli.metrics {
+        game = game.with_metrics(MetricsLog::create(path)?);
+    }
+    if cli.headless {
+        return game.run_headless(cli.generations);
+    }
     game.run()
 }
+
+fn run_bench(
+    backends: &[Backend],
+    quick: bool,
+    generations: usize,
+    output: Option<&Path>,
+    baseline: Option<&Path>,
+    threshold: f64,
+) -> Result<()> {
+    // Load the baseline first so a bad path fails before the long part
+    let baseline = baseline.map(Report::load).transpose()?;
+
+    let cases = bench::matrix(backends, quick);
+    let report = Report::run(&cases, generations, |m| {
+        println!("{:<28} {:>14.1} gen/s", m.name, m.gens_per_sec);
+    });
+    if let Some(path) = output {
+        report.save(path)?;
+    }
+
+    let Some(baseline) = baseline else {
+        return Ok(());
+    };
+    println!("\ncompared to baseline {}", baseline.version);
+    let mut regressions = 0;
+    for change in report.compare(&baseline) {
+        let regressed = change.is_regression(threshold);
+        regressions += regressed as usize;
+        println!(
+            "{:<28} {:>14.1} -> {:>14.1} gen/s {:>+8.1}%{}",
+            change.name,
+            change.baseline,
+            change.current,
+            change.percent(),
+            if regressed { "  REGRESSION" } else { "" }
+        );
+    }
+    if regressions > 0 {
+        bail!("{regressions} case(s) are more than {threshold}% slower than the baseline");
+    }
+    Ok(())
+}
@@ -0,0 +1,143 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::stats::{Outbreak, Traits};
+
+// Weight of the newest sample in the moving averages
+const SMOOTHING: f64 = 0.1;
+
+// Exponentially weighted moving average, the first sample is taken as is
+#[derive(Debug, Clone, Copy, Default)]
+struct Smoothed(Option<f64>);
+
+impl Smoothed {
+    fn add(&mut self, sample: f64) {
+        self.0 = Some(match self.0 {
+            Some(value) => value + SMOOTHING * (sample - value),
+            None => sample,
+        });
+    }
+
+    fn get(&self) -> f64 {
+        self.0.unwrap_or(0.0)
+    }
+}
+
+// Smoothed timings of the run loop. Frames are counted from the start of one
+// to the start of the next, so the frame rate includes sleeping.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    last_frame: Option<Instant>,
+    // Generations simulated since the last frame started
+    generations: usize,
+    frame: Smoothed,
+    gens_per_sec: Smoothed,
+    sim_per_gen: Smoothed,
+    render: Smoothed,
+}
+
+impl Metrics {
+    pub fn start_frame(&mut self) {
+        let now = Instant::now();
+        if let Some(last) = self.last_frame {
+            let elapsed = (now - last).as_secs_f64();
+            self.frame.add(elapsed);
+            self.gens_per_sec.add(self.generations as f64 / elapsed);
+        }
+        self.last_frame = Some(now);
+        self.generations = 0;
+    }
+
+    pub fn record_sim(&mut self, elapsed: Duration, generations: usize) {
+        if generations > 0 {
+            self.sim_per_gen
+                .add(elapsed.as_secs_f64() / generations as f64);
+            self.generations += generations;
+        }
+    }
+
+    pub fn record_render(&mut self, elapsed: Duration) {
+        self.render.add(elapsed.as_secs_f64());
+    }
+
+    pub fn rates(&self) -> Rates {
+        let frame = self.frame.get();
+        Rates {
+            fps: if frame > 0.0 { 1.0 / frame } else { 0.0 },
+            gens_per_sec: self.gens_per_sec.get(),
+            sim_ms_per_gen: self.sim_per_gen.get() * 1000.0,
+            render_ms: self.render.get() * 1000.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Rates {
+    pub fps: f64,
+    pub gens_per_sec: f64,
+    pub sim_ms_per_gen: f64,
+    pub render_ms: f64,
+}
+
+impl Rates {
+    pub fn summary(&self) -> String {
+        format!(
+            "fps: {:.1}  gen/s: {:.1}  sim: {:.3} ms/gen  render: {:.2} ms",
+            self.fps, self.gens_per_sec, self.sim_ms_per_gen, self.render_ms
+        )
+    }
+}
+
+// Population and mean traits of one species in a sample
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeciesSample {
+    pub name: String,
+    pub population: usize,
+    pub traits: Traits,
+    // What happened to its cells in the last generation
+    pub census: BTreeMap<&'static str, usize>,
+    // Infected and recovered cells, while the epidemic is on
+    #[serde(flatten)]
+    pub outbreak: Option<Outbreak>,
+}
+
+// One line of a metrics log
+#[derive(Debug, Clone, Serialize)]
+pub struct Sample {
+    pub generation: usize,
+    pub species: Vec<SpeciesSample>,
+    // Mean resource level, with the resource layer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<f64>,
+    #[serde(flatten)]
+    pub rates: Rates,
+}
+
+// Writes a sample per frame as JSON lines
+pub struct MetricsLog {
+    out: BufWriter<File>,
+}
+
+impl MetricsLog {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+        Ok(Self {
+            out: BufWriter::new(file),
+        })
+    }
+
+    pub fn write(&mut self, sample: &Sample) -> Result<()> {
+        serde_json::to_writer(&mut self.out, sample)?;
+        self.out.write_all(b"\n")?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+
+use rand::{rngs::StdRng, seq::SliceRandom};
+
+use crate::cell::CellType;
+
+// Cells an agent can move to in one generation
+const STEPS: [(i64, i64); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+// A live cell taking part in the movement phase
+#[derive(Debug, Clone, Copy)]
+pub struct Mover {
+    pub pos: (i64, i64),
+    pub kind: CellType,
+}
+
+// Every mover picks the empty cell next to it that `score` rates best, staying
+// put unless one scores higher than where it is, with ties between cells
+// broken at random. When several pick the same cell the one gaining most gets
+// it and ties go to the one listed first, the others stay. Only cells empty
+// before anyone moves are taken, so moves never chain or swap. `movers` has to
+// be in a fixed order for runs to repeat. Returns the moves as (from, to).
+pub fn plan(
+    movers: &[Mover],
+    mut is_empty: impl FnMut(i64, i64) -> bool,
+    // How much a mover of the species wants to be at the position
+    mut score: impl FnMut(CellType, i64, i64) -> i64,
+    rng: &mut StdRng,
+) -> Vec<((i64, i64), (i64, i64))> {
+    // Destination claimed so far, the mover that gets it and its gain
+    let mut claims: HashMap<(i64, i64), (usize, i64)> = HashMap::new();
+    let mut best = Vec::new();
+    for (i, mover) in movers.iter().enumerate() {
+        let (x, y) = mover.pos;
+        let here = score(mover.kind, x, y);
+        let mut top = here;
+        best.clear();
+        for (dx, dy) in STEPS {
+            let to = (x + dx, y + dy);
+            if !is_empty(to.0, to.1) {
+                continue;
+            }
+            let there = score(mover.kind, to.0, to.1);
+            if there > top {
+                top = there;
+                best.clear();
+            }
+            if there == top && there > here {
+                best.push(to);
+            }
+        }
+        let Some(&to) = best.choose(rng) else {
+            continue;
+        };
+        let gain = top - here;
+        claims
+            .entry(to)
+            .and_modify(|claim| {
+                if gain > claim.1 {
+                    *claim = (i, gain);
+                }
+            })
+            .or_insert((i, gain));
+    }
+
+    let mut moves: Vec<_> = claims.into_iter().map(|(to, (i, _))| (i, to)).collect();
+    moves.sort_unstable();
+    moves
+        .into_iter()
+        .map(|(i, to)| (movers[i].pos, to))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn mover(pos: (i64, i64)) -> Mover {
+        Mover {
+            pos,
+            kind: CellType::PREY,
+        }
+    }
+
+    #[test]
+    fn the_biggest_gain_gets_a_contested_cell() {
+        // Only (1, 0) is worth moving to, from a worse or an equal cell
+        let empty = |x, y| (x, y) == (1, 0);
+        for (scores, winner) in [([0, -1], (2, 0)), ([0, 0], (0, 0))] {
+            let movers = [mover((0, 0)), mover((2, 0))];
+            let score = |_, x, y| match (x, y) {
+                (1, 0) => 1,
+                (0, 0) => scores[0],
+                _ => scores[1],
+            };
+            let mut rng = StdRng::seed_from_u64(1);
+            assert_eq!(plan(&movers, empty, score, &mut rng), [(winner, (1, 0))]);
+        }
+    }
+
+    #[test]
+    fn plans_repeat_with_the_seed() {
+        // A crowd on a flat field, where every move is a tie broken at random
+        let movers: Vec<_> = (0..5)
+            .flat_map(|y| (0..5).map(move |x| mover((2 * x, y))))
+            .collect();
+        let occupied: HashSet<_> = movers.iter().map(|m| m.pos).collect();
+        let empty = |x, y| !occupied.contains(&(x, y));
+        let score = |_, x: i64, _| if x % 2 == 0 { 0 } else { 1 };
+        let moves = |seed| plan(&movers, empty, score, &mut StdRng::seed_from_u64(seed));
+        assert_eq!(moves(3), moves(3));
+        assert_ne!(moves(3), moves(4));
+    }
+
+    #[test]
+    fn moves_only_into_cells_that_were_empty() {
+        // A 6x6 field inside walls, with a wall down the middle
+        let wall = |x: i64, y: i64| !(0..6).contains(&x) || !(0..6).contains(&y) || x == 3;
+        let movers: Vec<_> = [(0, 0), (1, 1), (2, 2), (2, 3), (4, 4), (5, 5), (5, 0)]
+            .into_iter()
+            .map(mover)
+            .collect();
+        let occupied: HashSet<_> = movers.iter().map(|m| m.pos).collect();
+        let empty = |x, y| !wall(x, y) && !occupied.contains(&(x, y));
+        // Everyone is drawn towards the wall and past the edges
+        let score = |_, x: i64, y: i64| -(x - 3).abs() + y;
+        for seed in 0..20 {
+            let moves = plan(&movers, empty, score, &mut StdRng::seed_from_u64(seed));
+            assert!(!moves.is_empty());
+            let mut taken = HashSet::new();
+            for (from, to) in moves {
+                assert!(occupied.contains(&from));
+                assert!(empty(to.0, to.1), "{from:?} moved to {to:?}");
+                assert!((from.0 - to.0).abs() <= 1 && (from.1 - to.1).abs() <= 1);
+                assert!(taken.insert(to), "two movers took {to:?}");
+            }
+        }
+    }
+}
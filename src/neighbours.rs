@@ -0,0 +1,199 @@
+use crate::cell::{Cell, CellState, CellType, Health};
+use crate::grid::Grid;
+use crate::species::{Rules, MAX_SPECIES};
+
+// Live cells of each species around a cell
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Neighbours {
+    counts: [u32; MAX_SPECIES],
+    infected: [u32; MAX_SPECIES],
+}
+
+impl Neighbours {
+    pub fn add(&mut self, cell: Cell) {
+        if let CellState::Alive { health, .. } = cell.state() {
+            let kind = cell.kind().index();
+            self.counts[kind] += 1;
+            if health == Health::Infected {
+                self.infected[kind] += 1;
+            }
+        }
+    }
+
+    pub fn remove(&mut self, cell: Cell) {
+        let mut own = Neighbours::default();
+        own.add(cell);
+        for kind in 0..MAX_SPECIES {
+            self.counts[kind] -= own.counts[kind];
+            self.infected[kind] -= own.infected[kind];
+        }
+    }
+
+    pub fn of(&self, kind: CellType) -> usize {
+        self.counts[kind.index()] as usize
+    }
+
+    pub fn infected(&self, kind: CellType) -> usize {
+        self.infected[kind.index()] as usize
+    }
+
+    pub fn all_infected(&self) -> usize {
+        self.infected.iter().map(|&count| count as usize).sum()
+    }
+
+    // Live cells of the species set in `species`, one bit per species
+    pub fn sum(&self, species: u16) -> usize {
+        (0..MAX_SPECIES)
+            .filter(|kind| species & 1 << kind != 0)
+            .map(|kind| self.counts[kind] as usize)
+            .sum()
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().map(|&count| count as usize).sum()
+    }
+}
+
+// Summed-area tables of the live cells and infected cells of each species,
+// the latter only while the epidemic is on. After
+// `build`, the number of each in any axis aligned rectangle is four lookups,
+// so square neighbourhoods cost the same regardless of radius.
+#[derive(Debug, Clone, Default)]
+pub struct SummedArea {
+    w: usize,
+    h: usize,
+    counts: Vec<Vec<u32>>,
+    infected: Vec<Vec<u32>>,
+}
+
+impl SummedArea {
+    pub fn build(&mut self, map: &Grid<Cell>, rules: &[Rules], disease: bool) {
+        self.w = map.width();
+        self.h = map.height();
+        // One extra leading row and column of zeros avoids edge cases in `sum`
+        let stride = self.w + 1;
+        let len = stride * (self.h + 1);
+        let species = rules.len();
+        let sick = if disease { species } else { 0 };
+        for (tables, kinds) in [(&mut self.counts, species), (&mut self.infected, sick)] {
+            tables.resize_with(kinds, Vec::new);
+            for table in tables.iter_mut() {
+                table.clear();
+                table.resize(len, 0);
+            }
+        }
+
+        for (y, row) in map.rows().enumerate() {
+            let mut counts = Neighbours::default();
+            for (x, cell) in row.iter().enumerate() {
+                counts.add(*cell);
+                let above = y * stride + x + 1;
+                let here = (y + 1) * stride + x + 1;
+                for kind in 0..species {
+                    self.counts[kind][here] = self.counts[kind][above] + counts.counts[kind];
+                }
+                for kind in 0..sick {
+                    self.infected[kind][here] = self.infected[kind][above] + counts.infected[kind];
+                }
+            }
+        }
+    }
+
+    // Live cells in the square of `radius` around (x, y), clipped to the grid
+    // and including the cell itself
+    pub fn square(&self, x: usize, y: usize, radius: usize) -> Neighbours {
+        let x0 = x.saturating_sub(radius);
+        let y0 = y.saturating_sub(radius);
+        let x1 = (x + radius + 1).min(self.w);
+        let y1 = (y + radius + 1).min(self.h);
+        let mut neighbours = Neighbours::default();
+        for kind in 0..self.counts.len() {
+            neighbours.counts[kind] = self.sum(&self.counts[kind], x0, y0, x1, y1);
+        }
+        for kind in 0..self.infected.len() {
+            neighbours.infected[kind] = self.sum(&self.infected[kind], x0, y0, x1, y1);
+        }
+        neighbours
+    }
+
+    fn sum(&self, table: &[u32], x0: usize, y0: usize, x1: usize, y1: usize) -> u32 {
+        let stride = self.w + 1;
+        let total = table[y1 * stride + x1] + table[y0 * stride + x0];
+        total - table[y0 * stride + x1] - table[y1 * stride + x0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::config::Config;
+    use crate::species;
+
+    fn random_map(w: usize, h: usize, seed: u64) -> Grid<Cell> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut map = Grid::new(w, h, Cell::default());
+        for y in 0..h {
+            for x in 0..w {
+                if rng.gen_bool(0.4) {
+                    let health = if rng.gen_bool(0.2) {
+                        Health::Infected
+                    } else {
+                        Health::Susceptible
+                    };
+                    let state = CellState::Alive {
+                        age: 1,
+                        energy: rng.gen_range(0..20),
+                        health,
+                    };
+                    map[(x, y)] = Cell::new(state, CellType(rng.gen_range(0..2)));
+                }
+            }
+        }
+        map
+    }
+
+    #[test]
+    fn squares_match_counting_every_cell() {
+        let rules = species::default_web().rules(&Config::default());
+        let map = random_map(23, 17, 5);
+        let mut counts = SummedArea::default();
+        counts.build(&map, &rules, true);
+        for radius in [1, 2, 5] {
+            for y in 0..map.height() {
+                for x in 0..map.width() {
+                    let mut expected = Neighbours::default();
+                    for ny in y.saturating_sub(radius)..(y + radius + 1).min(map.height()) {
+                        for nx in x.saturating_sub(radius)..(x + radius + 1).min(map.width()) {
+                            expected.add(map[(nx, ny)]);
+                        }
+                    }
+                    assert_eq!(counts.square(x, y, radius), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_out_infected_cells_without_the_epidemic() {
+        let rules = species::default_web().rules(&Config::default());
+        let map = random_map(8, 8, 6);
+        let mut counts = SummedArea::default();
+        counts.build(&map, &rules, false);
+        assert_eq!(counts.square(4, 4, 8).all_infected(), 0);
+    }
+
+    #[test]
+    fn removing_a_cell_undoes_adding_it() {
+        let map = random_map(4, 4, 7);
+        let mut neighbours = Neighbours::default();
+        for cell in map.iter() {
+            neighbours.add(*cell);
+        }
+        let all = neighbours;
+        neighbours.add(map[(1, 1)]);
+        neighbours.remove(map[(1, 1)]);
+        assert_eq!(neighbours, all);
+    }
+}
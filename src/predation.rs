@@ -0,0 +1,492 @@
+use std::collections::{HashMap, HashSet};
+
+use rand::{rngs::StdRng, Rng};
+
+use crate::cell::{CellType, Genome};
+
+// What the hunt decided for a cell this generation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Hunt {
+    #[default]
+    None,
+    // Predator that caught its prey and/or had a newborn this generation
+    Predator {
+        fed: bool,
+        bred: bool,
+    },
+    // Prey that was caught
+    Eaten,
+    // Prey caught by a predator with enough energy to breed, whose newborn
+    // takes its cell. Holds the predator's species and genome.
+    Offspring(CellType, Genome),
+    // Empty cell a predator of the species puts its newborn into
+    Born(CellType),
+}
+
+// A predator taking part in the hunt
+#[derive(Debug, Clone, Copy)]
+pub struct Hunter {
+    pub pos: (i64, i64),
+    pub kind: CellType,
+    pub energy: usize,
+    // Has enough energy to breed
+    pub breeder: bool,
+    pub genome: Genome,
+}
+
+// Every hunter picks a random prey it can catch within `offsets` of it. When
+// several pick the same prey the hungriest gets it and ties go to the one
+// listed first, the others go without this generation. Each prey gets away
+// with chance `escape`. A hunter that is caught itself doesn't eat, so when A
+// catches B and B catches C, C is only eaten if B wasn't. In a ring of hunters
+// catching each other the one listed first strikes first. Then every
+// breeder that is still alive and hasn't had its newborn in its prey's cell
+// breeds with the reproduction chance of its genome, into a random vacant cell
+// around it unless breeders listed before it took them all. `hunters` has to
+// be in a fixed order for runs to repeat.
+// Returns the cells the hunt decided on.
+#[allow(clippy::too_many_arguments)]
+pub fn hunt(
+    hunters: &[Hunter],
+    offsets: &[(isize, isize)],
+    // Whether a hunter at the position can reach the cell at the offset from it
+    mut sight: impl FnMut((i64, i64), (isize, isize)) -> bool,
+    // Whether a hunter of the species can catch the cell at the position
+    mut catchable: impl FnMut(CellType, i64, i64) -> bool,
+    // Whether a newborn can be put into the cell at the position
+    mut vacant: impl FnMut(i64, i64) -> bool,
+    // Breeders put their newborn into the cell of their prey
+    offspring: bool,
+    escape: f64,
+    rng: &mut StdRng,
+) -> Vec<((i64, i64), Hunt)> {
+    let mut known = HashMap::new();
+    // Prey claimed so far and the hunter that gets it
+    let mut claims: HashMap<(i64, i64), usize> = HashMap::new();
+    let mut targets = Vec::new();
+    for (i, hunter) in hunters.iter().enumerate() {
+        targets.clear();
+        for &(dx, dy) in offsets {
+            let pos = (hunter.pos.0 + dx as i64, hunter.pos.1 + dy as i64);
+            if sight(hunter.pos, (dx, dy))
+                && *known
+                    .entry((hunter.kind, pos))
+                    .or_insert_with(|| catchable(hunter.kind, pos.0, pos.1))
+            {
+                targets.push(pos);
+            }
+        }
+        if targets.is_empty() {
+            continue;
+        }
+        let target = targets[rng.gen_range(0..targets.len())];
+        claims
+            .entry(target)
+            .and_modify(|best| {
+                if hunter.energy < hunters[*best].energy {
+                    *best = i;
+                }
+            })
+            .or_insert(i);
+    }
+
+    let mut catches: Vec<_> = claims.into_iter().map(|(prey, i)| (i, prey)).collect();
+    catches.sort_unstable();
+    catches.retain(|_| !rng.gen_bool(escape));
+    let stands = settle(hunters, &catches);
+    catches.retain(|&(i, _)| stands[i]);
+    let caught: HashSet<_> = catches.iter().map(|&(_, prey)| prey).collect();
+
+    let mut outcome = Vec::with_capacity(catches.len() * 2);
+    // Whether each hunter ate and whether it had a newborn
+    let mut fed = vec![false; hunters.len()];
+    let mut bred = vec![false; hunters.len()];
+    for (i, prey) in catches {
+        let hunter = hunters[i];
+        let meal = if offspring && hunter.breeder {
+            bred[i] = true;
+            Hunt::Offspring(hunter.kind, hunter.genome)
+        } else {
+            Hunt::Eaten
+        };
+        fed[i] = true;
+        outcome.push((prey, meal));
+    }
+
+    let mut nurseries = HashSet::new();
+    for (i, hunter) in hunters.iter().enumerate() {
+        if !hunter.breeder || bred[i] || caught.contains(&hunter.pos) {
+            continue;
+        }
+        if !rng.gen_bool(hunter.genome.reproduction_chance()) {
+            continue;
+        }
+        targets.clear();
+        for &(dx, dy) in offsets {
+            let pos = (hunter.pos.0 + dx as i64, hunter.pos.1 + dy as i64);
+            if !nurseries.contains(&pos) && sight(hunter.pos, (dx, dy)) && vacant(pos.0, pos.1) {
+                targets.push(pos);
+            }
+        }
+        if targets.is_empty() {
+            continue;
+        }
+        let nursery = targets[rng.gen_range(0..targets.len())];
+        nurseries.insert(nursery);
+        bred[i] = true;
+        outcome.push((nursery, Hunt::Born(hunter.kind)));
+    }
+
+    for (i, hunter) in hunters.iter().enumerate() {
+        if fed[i] || bred[i] {
+            let (fed, bred) = (fed[i], bred[i]);
+            outcome.push((hunter.pos, Hunt::Predator { fed, bred }));
+        }
+    }
+    outcome
+}
+
+// Whether the catch of each hunter stands, given the catches (hunter, prey)
+// that got through. Each prey has one catcher and each hunter one prey, so the
+// catches form chains and rings. Down a chain from a hunter nobody caught every
+// other catch stands, the ones in between are by hunters that were eaten. A
+// ring is settled the same way from its first listed hunter, which can't be
+// caught back.
+fn settle(hunters: &[Hunter], catches: &[(usize, (i64, i64))]) -> Vec<bool> {
+    let at: HashMap<_, _> = hunters
+        .iter()
+        .enumerate()
+        .map(|(i, h)| (h.pos, i))
+        .collect();
+    // The hunter each hunter caught, if its prey is one
+    let mut next = vec![None; hunters.len()];
+    let mut caught = vec![false; hunters.len()];
+    for &(i, prey) in catches {
+        if let Some(&j) = at.get(&prey) {
+            next[i] = Some(j);
+            caught[j] = true;
+        }
+    }
+
+    let mut stands = vec![false; hunters.len()];
+    let mut settled = vec![false; hunters.len()];
+    for &(i, _) in catches.iter().filter(|&&(i, _)| !caught[i]) {
+        let mut strikes = true;
+        let mut hunter = Some(i);
+        while let Some(j) = hunter {
+            (stands[j], settled[j]) = (strikes, true);
+            strikes = !strikes;
+            hunter = next[j];
+        }
+    }
+    // What is left are rings, in order of their first listed hunter
+    for &(i, _) in catches {
+        let mut strikes = true;
+        let mut hunter = i;
+        while !settled[hunter] {
+            (stands[hunter], settled[hunter]) = (strikes, true);
+            strikes = !strikes;
+            match next[hunter] {
+                // Back at the first one, which struck before it could be caught
+                Some(j) if j == i => stands[hunter] = false,
+                Some(j) => hunter = j,
+                None => break,
+            }
+        }
+    }
+    stands
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    const MOORE: [(isize, isize); 8] = [
+        (-1, -1),
+        (0, -1),
+        (1, -1),
+        (-1, 0),
+        (1, 0),
+        (-1, 1),
+        (0, 1),
+        (1, 1),
+    ];
+
+    fn hunter(pos: (i64, i64), kind: u8, energy: usize) -> Hunter {
+        Hunter {
+            pos,
+            kind: CellType(kind),
+            energy,
+            breeder: false,
+            genome: Genome::default(),
+        }
+    }
+
+    // Breeds whenever it can
+    fn breeder(pos: (i64, i64), kind: u8) -> Hunter {
+        Hunter {
+            breeder: true,
+            genome: Genome::new(100, 1.0, 0, 0),
+            ..hunter(pos, kind, 20)
+        }
+    }
+
+    fn everywhere(_: (i64, i64), _: (isize, isize)) -> bool {
+        true
+    }
+
+    fn sorted(mut outcome: Vec<((i64, i64), Hunt)>) -> Vec<((i64, i64), Hunt)> {
+        outcome.sort_by_key(|&(pos, _)| pos);
+        outcome
+    }
+
+    #[test]
+    fn the_hungriest_hunter_gets_a_contested_prey() {
+        let prey = |_, x, y| (x, y) == (1, 0);
+        let fed = Hunt::Predator {
+            fed: true,
+            bred: false,
+        };
+        for (hunters, winner) in [
+            ([hunter((0, 0), 1, 5), hunter((2, 0), 1, 2)], (2, 0)),
+            ([hunter((0, 0), 1, 3), hunter((2, 0), 1, 3)], (0, 0)),
+        ] {
+            let mut rng = StdRng::seed_from_u64(1);
+            let outcome = hunt(
+                &hunters,
+                &MOORE,
+                everywhere,
+                prey,
+                |_, _| false,
+                false,
+                0.0,
+                &mut rng,
+            );
+            let mut expected = vec![((1, 0), Hunt::Eaten), (winner, fed)];
+            expected.sort_by_key(|&(pos, _)| pos);
+            assert_eq!(sorted(outcome), expected);
+        }
+    }
+
+    #[test]
+    fn a_caught_hunter_goes_hungry() {
+        // Species 2 eats 1, which eats 0
+        let hunters = [hunter((0, 0), 1, 5), hunter((-1, 0), 2, 5)];
+        let catchable = |kind: CellType, x, y| match kind.0 {
+            1 => (x, y) == (1, 0),
+            _ => (x, y) == (0, 0),
+        };
+        let mut rng = StdRng::seed_from_u64(2);
+        let outcome = hunt(
+            &hunters,
+            &MOORE,
+            everywhere,
+            catchable,
+            |_, _| false,
+            false,
+            0.0,
+            &mut rng,
+        );
+        let fed = Hunt::Predator {
+            fed: true,
+            bred: false,
+        };
+        assert_eq!(sorted(outcome), [((-1, 0), fed), ((0, 0), Hunt::Eaten)]);
+    }
+
+    #[test]
+    fn catches_along_a_chain_alternate() {
+        // Species 3 eats 2, which eats 1, which eats 0, each the cell to its right
+        let hunters = [
+            hunter((0, 0), 3, 5),
+            hunter((1, 0), 2, 5),
+            hunter((2, 0), 1, 5),
+        ];
+        let catchable = |kind: CellType, x, y| (x, y) == (4 - kind.0 as i64, 0);
+        let mut rng = StdRng::seed_from_u64(2);
+        let outcome = hunt(
+            &hunters,
+            &MOORE,
+            everywhere,
+            catchable,
+            |_, _| false,
+            false,
+            0.0,
+            &mut rng,
+        );
+        let fed = Hunt::Predator {
+            fed: true,
+            bred: false,
+        };
+        // The second is eaten before it eats the third, which eats the prey
+        assert_eq!(
+            sorted(outcome),
+            [
+                ((0, 0), fed),
+                ((1, 0), Hunt::Eaten),
+                ((2, 0), fed),
+                ((3, 0), Hunt::Eaten)
+            ]
+        );
+    }
+
+    #[test]
+    fn the_first_hunter_of_a_ring_strikes_first() {
+        // Each hunter catches the next one, the last one the first
+        let hunters = [
+            hunter((0, 0), 0, 5),
+            hunter((1, 0), 1, 5),
+            hunter((1, 1), 2, 5),
+        ];
+        let catchable = |kind: CellType, x, y| match kind.0 {
+            0 => (x, y) == (1, 0),
+            1 => (x, y) == (1, 1),
+            _ => (x, y) == (0, 0),
+        };
+        let fed = Hunt::Predator {
+            fed: true,
+            bred: false,
+        };
+        for ring in [&hunters[..2], &hunters[..]] {
+            let mut rng = StdRng::seed_from_u64(2);
+            let outcome = hunt(
+                ring,
+                &MOORE,
+                everywhere,
+                catchable,
+                |_, _| false,
+                false,
+                0.0,
+                &mut rng,
+            );
+            assert_eq!(sorted(outcome), [((0, 0), fed), ((1, 0), Hunt::Eaten)]);
+        }
+    }
+
+    #[test]
+    fn prey_gets_away_with_the_escape_chance() {
+        let hunters = [hunter((0, 0), 1, 5)];
+        let prey = |_, x, y| (x, y) == (1, 0);
+        let mut rng = StdRng::seed_from_u64(6);
+        let caught = |escape, rng: &mut StdRng| {
+            let outcome = hunt(
+                &hunters,
+                &MOORE,
+                everywhere,
+                prey,
+                |_, _| false,
+                false,
+                escape,
+                rng,
+            );
+            outcome.contains(&((1, 0), Hunt::Eaten))
+        };
+        assert!((0..50).all(|_| caught(0.0, &mut rng)));
+        assert!((0..50).all(|_| !caught(1.0, &mut rng)));
+        let escapes = (0..2000).filter(|_| !caught(0.25, &mut rng)).count();
+        assert!((400..600).contains(&escapes), "{escapes} escapes");
+    }
+
+    #[test]
+    fn breeders_breed_with_their_reproduction_chance() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let newborns = |chance, rng: &mut StdRng| {
+            let parent = Hunter {
+                genome: Genome::new(100, chance, 0, 0),
+                ..breeder((0, 0), 1)
+            };
+            let vacant = |x, y| (x, y) != (0, 0);
+            let outcome = hunt(
+                &[parent],
+                &MOORE,
+                everywhere,
+                |_, _, _| false,
+                vacant,
+                false,
+                0.0,
+                rng,
+            );
+            outcome
+                .iter()
+                .filter(|(_, hunt)| matches!(hunt, Hunt::Born(_)))
+                .count()
+        };
+        assert!((0..50).all(|_| newborns(0.0, &mut rng) == 0));
+        assert!((0..50).all(|_| newborns(1.0, &mut rng) == 1));
+        let born: usize = (0..2000).map(|_| newborns(0.5, &mut rng)).sum();
+        assert!((900..1100).contains(&born), "{born} newborns");
+    }
+
+    #[test]
+    fn a_breeder_has_one_newborn_in_a_vacant_cell() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let outcome = hunt(
+            &[breeder((0, 0), 1)],
+            &MOORE,
+            everywhere,
+            |_, _, _| false,
+            |x, y| (x, y) != (0, 0),
+            false,
+            0.0,
+            &mut rng,
+        );
+        let [(nursery, Hunt::Born(CellType(1))), parent] = outcome[..] else {
+            panic!("{outcome:?}");
+        };
+        assert!(MOORE.contains(&(nursery.0 as isize, nursery.1 as isize)));
+        let bred = Hunt::Predator {
+            fed: false,
+            bred: true,
+        };
+        assert_eq!(parent, ((0, 0), bred));
+    }
+
+    #[test]
+    fn breeders_share_the_vacant_cells() {
+        // Only (1, 0) is free, the breeder listed first takes it
+        let hunters = [breeder((0, 0), 1), breeder((2, 0), 1)];
+        let mut rng = StdRng::seed_from_u64(4);
+        let outcome = hunt(
+            &hunters,
+            &MOORE,
+            everywhere,
+            |_, _, _| false,
+            |x, y| (x, y) == (1, 0),
+            false,
+            0.0,
+            &mut rng,
+        );
+        let bred = Hunt::Predator {
+            fed: false,
+            bred: true,
+        };
+        assert_eq!(
+            sorted(outcome),
+            [((0, 0), bred), ((1, 0), Hunt::Born(CellType(1)))]
+        );
+    }
+
+    #[test]
+    fn offspring_in_the_prey_cell_is_the_only_newborn() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let parent = breeder((0, 0), 1);
+        let outcome = hunt(
+            &[parent],
+            &MOORE,
+            everywhere,
+            |_, x, y| (x, y) == (1, 0),
+            |x, y| (x, y) != (0, 0) && (x, y) != (1, 0),
+            true,
+            0.0,
+            &mut rng,
+        );
+        let both = Hunt::Predator {
+            fed: true,
+            bred: true,
+        };
+        let offspring = Hunt::Offspring(parent.kind, parent.genome);
+        assert_eq!(sorted(outcome), [((0, 0), both), ((1, 0), offspring)]);
+    }
+}
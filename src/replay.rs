@@ -0,0 +1,207 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::cell::CellType;
+use crate::config::{Config, Param};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+// Anything that changes a run besides the seed and the rules themselves
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    Set(Param),
+    Spawn { x: usize, y: usize, kind: CellType },
+    // Kills every cell within `radius` of (x, y)
+    Meteor { x: usize, y: usize, radius: usize },
+}
+
+// An event together with the generation it was applied to, before stepping
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Entry {
+    pub generation: usize,
+    pub event: Event,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    seed: u64,
+    config: Config,
+}
+
+// A complete, re-simulatable description of a run: one JSON header line with
+// the seed and config, followed by one line per event
+pub struct EventLog {
+    pub config: Config,
+    pub entries: Vec<Entry>,
+}
+
+impl EventLog {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+        let mut lines = BufReader::new(file).lines();
+
+        let header = lines.next().context("event log is empty")??;
+        let header: Header = serde_json::from_str(&header).context("invalid event log header")?;
+        let mut config = header.config.with_offsets();
+        config.seed = header.seed;
+
+        let mut entries = Vec::new();
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push(serde_json::from_str(&line).context("invalid event log entry")?);
+        }
+        Ok(Self { config, entries })
+    }
+
+    // Event logs start with a header carrying the seed, casts don't
+    pub fn sniff(path: &Path) -> Result<bool> {
+        let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+        let mut header = String::new();
+        BufReader::new(file).read_line(&mut header)?;
+        Ok(serde_json::from_str::<Header>(&header).is_ok())
+    }
+}
+
+pub struct EventLogWriter {
+    path: PathBuf,
+    config: Config,
+    out: BufWriter<File>,
+}
+
+impl EventLogWriter {
+    pub fn create(path: &Path, config: Config) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+        let mut out = BufWriter::new(file);
+        let header = Header {
+            seed: config.seed,
+            config,
+        };
+        serde_json::to_writer(&mut out, &header)?;
+        out.write_all(b"\n")?;
+        out.flush()?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            config,
+            out,
+        })
+    }
+
+    // Starts the log over with `entries`, used when the run branches off an
+    // earlier generation and the events after it no longer happened
+    pub fn rewrite(&mut self, entries: &[Entry]) -> Result<()> {
+        *self = EventLogWriter::create(&self.path, self.config)?;
+        for entry in entries {
+            self.write(*entry)?;
+        }
+        Ok(())
+    }
+
+    pub fn write(&mut self, entry: Entry) -> Result<()> {
+        serde_json::to_writer(&mut self.out, &entry)?;
+        self.out.write_all(b"\n")?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_path;
+
+    #[test]
+    fn log_round_trips() {
+        let path = temp_path("round-trip.log");
+        let config = Config {
+            seed: 42,
+            mutation: 0.25,
+            ..Config::default()
+        };
+        let entries = [
+            Entry {
+                generation: 3,
+                event: Event::Set(Param::Age(70)),
+            },
+            Entry {
+                generation: 5,
+                event: Event::Spawn {
+                    x: 1,
+                    y: 2,
+                    kind: CellType::PREDETOR,
+                },
+            },
+            Entry {
+                generation: 8,
+                event: Event::Meteor {
+                    x: 4,
+                    y: 5,
+                    radius: 6,
+                },
+            },
+        ];
+        let mut writer = EventLogWriter::create(&path, config).unwrap();
+        for entry in entries {
+            writer.write(entry).unwrap();
+        }
+        drop(writer);
+
+        assert!(EventLog::sniff(&path).unwrap());
+        let log = EventLog::load(&path).unwrap();
+        assert_eq!(log.config.seed, 42);
+        assert_eq!(log.config.mutation, 0.25);
+        assert_eq!(
+            serde_json::to_string(&log.entries).unwrap(),
+            serde_json::to_string(&entries).unwrap()
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rewrite_drops_later_entries() {
+        let path = temp_path("rewrite.log");
+        let entry = |generation| Entry {
+            generation,
+            event: Event::Set(Param::Mutation(0.5)),
+        };
+        let mut writer = EventLogWriter::create(&path, Config::default()).unwrap();
+        writer.write(entry(1)).unwrap();
+        writer.write(entry(9)).unwrap();
+        writer.rewrite(&[entry(1)]).unwrap();
+        drop(writer);
+
+        let log = EventLog::load(&path).unwrap();
+        assert_eq!(log.entries.len(), 1);
+        assert_eq!(log.entries[0].generation, 1);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reads_species_names_of_older_logs() {
+        let entry: Entry = serde_json::from_str(
+            r#"{"generation":1,"event":{"spawn":{"x":0,"y":0,"kind":"predetor"}}}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            entry.event,
+            Event::Spawn {
+                kind: CellType::PREDETOR,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn casts_are_not_event_logs() {
+        let path = temp_path("sniff.cast");
+        std::fs::write(&path, "{\"version\":2,\"width\":4,\"height\":2}\n").unwrap();
+        assert!(!EventLog::sniff(&path).unwrap());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,158 @@
+use crossterm::style::Color;
+
+use crate::grid::Grid;
+
+// Background colours from bare to fully grown, dark so the cells drawn over
+// them stay readable
+const SHADES: [u8; 4] = [16, 52, 58, 22];
+
+// Resource level of every cell of the map, between 0 and the capacity of 1,
+// that prey graze on. Each generation the grazers eat, then the field spreads
+// between each cell and the four next to it and regrows toward its capacity.
+#[derive(Debug, Clone, Default)]
+pub struct Resources {
+    levels: Grid<f32>,
+    // The levels after grazing, kept between generations to be written over
+    eaten: Grid<f32>,
+}
+
+impl Resources {
+    // A fully grown field
+    pub fn new(w: usize, h: usize) -> Self {
+        Self {
+            levels: Grid::new(w, h, 1.0),
+            eaten: Grid::default(),
+        }
+    }
+
+    pub fn level(&self, x: usize, y: usize) -> f64 {
+        self.levels[(x, y)] as f64
+    }
+
+    pub fn mean(&self) -> f64 {
+        let cells = self.levels.width() * self.levels.height();
+        self.levels.iter().map(|&level| level as f64).sum::<f64>() / cells.max(1) as f64
+    }
+
+    // Writes the field of the next generation into `next`, reusing its grids.
+    // `grazed` tells whether a grazer lives at (x, y) after the step. Each eats
+    // `grazing`, `diffusion` of the difference to each cell next to it is split
+    // four ways between the two, and `regrowth` of what is missing to the
+    // capacity grows back.
+    pub fn grow(
+        &self,
+        next: &mut Resources,
+        grazed: impl Fn(usize, usize) -> bool,
+        grazing: f64,
+        diffusion: f64,
+        regrowth: f64,
+    ) {
+        let (w, h) = (self.levels.width(), self.levels.height());
+        let eaten = &mut next.eaten;
+        if eaten.width() != w || eaten.height() != h {
+            *eaten = Grid::new(w, h, 0.0);
+        }
+        for (y, (row, levels)) in eaten.rows_mut().zip(self.levels.rows()).enumerate() {
+            for (x, (level, &before)) in row.iter_mut().zip(levels).enumerate() {
+                *level = if grazed(x, y) {
+                    (before - grazing as f32).max(0.0)
+                } else {
+                    before
+                };
+            }
+        }
+
+        let levels = &mut next.levels;
+        if levels.width() != w || levels.height() != h {
+            *levels = Grid::new(w, h, 0.0);
+        }
+        let eaten = &next.eaten;
+        for (y, row) in levels.rows_mut().enumerate() {
+            for (x, level) in row.iter_mut().enumerate() {
+                // What flows in from the cells next to it less what flows out,
+                // so the field spreads without changing its total. The edges
+                // only trade with the cells inside the map.
+                let here = eaten[(x, y)];
+                let mut flow = 0.0;
+                for (nx, ny) in [
+                    (x.wrapping_sub(1), y),
+                    (x + 1, y),
+                    (x, y.wrapping_sub(1)),
+                    (x, y + 1),
+                ] {
+                    if let Some(neighbour) = eaten.get(nx, ny) {
+                        flow += neighbour - here;
+                    }
+                }
+                let spread = here + diffusion as f32 / 4.0 * flow;
+                *level = (spread + regrowth as f32 * (1.0 - spread)).clamp(0.0, 1.0);
+            }
+        }
+    }
+
+    // Background under an empty cell at (x, y)
+    pub fn color(&self, x: usize, y: usize) -> Color {
+        let level = self.levels[(x, y)];
+        let shade = ((level * SHADES.len() as f32) as usize).min(SHADES.len() - 1);
+        Color::AnsiValue(SHADES[shade])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn total(resources: &Resources) -> f64 {
+        resources.mean() * 6.0 * 4.0
+    }
+
+    // A field of 6x4 with a bare column down x = 1
+    fn patchy() -> Resources {
+        let mut resources = Resources::new(6, 4);
+        for y in 0..4 {
+            resources.levels[(1, y)] = 0.0;
+        }
+        resources
+    }
+
+    #[test]
+    fn regrows_up_to_the_capacity() {
+        let mut resources = patchy();
+        let mut next = Resources::default();
+        for _ in 0..200 {
+            resources.grow(&mut next, |_, _| false, 0.2, 0.0, 0.1);
+            std::mem::swap(&mut resources, &mut next);
+            assert!(resources.levels.iter().all(|&level| level <= 1.0));
+        }
+        assert!(resources.level(1, 0) > 0.99);
+        assert_eq!(resources.level(0, 0), 1.0);
+    }
+
+    #[test]
+    fn spreads_without_changing_the_total() {
+        let mut resources = patchy();
+        let before = total(&resources);
+        let mut next = Resources::default();
+        for _ in 0..20 {
+            resources.grow(&mut next, |_, _| false, 0.2, 0.5, 0.0);
+            std::mem::swap(&mut resources, &mut next);
+        }
+        assert!((total(&resources) - before).abs() < 1e-4);
+        // The bare column filled up from both sides, the corners gave some
+        assert!(resources.level(1, 0) > 0.5);
+        assert!(resources.level(0, 0) < 1.0);
+    }
+
+    #[test]
+    fn grazers_eat_their_share() {
+        let resources = Resources::new(6, 4);
+        let mut next = Resources::default();
+        resources.grow(&mut next, |x, y| (x, y) == (2, 2), 0.25, 0.0, 0.0);
+        assert_eq!(next.level(2, 2), 0.75);
+        assert!((total(&next) - (total(&resources) - 0.25)).abs() < 1e-4);
+        // Nothing is left to eat below 0
+        let mut bare = Resources::default();
+        patchy().grow(&mut bare, |x, _| x == 1, 0.25, 0.0, 0.0);
+        assert_eq!(bare.level(1, 3), 0.0);
+    }
+}
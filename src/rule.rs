@@ -0,0 +1,139 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+// A two-state outer totalistic rule in B/S notation, e.g. B3/S23 for Conway's
+// Life. Bit `n` of `birth` / `survive` is set when `n` live neighbours cause a
+// birth / let a cell survive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct LifeRule {
+    pub birth: u16,
+    pub survive: u16,
+}
+
+impl LifeRule {
+    // B3/S23
+    pub const CONWAY: LifeRule = LifeRule {
+        birth: 1 << 3,
+        survive: (1 << 2) | (1 << 3),
+    };
+
+    pub fn births(&self, neighbours: usize) -> bool {
+        neighbours <= 8 && self.birth & (1 << neighbours) != 0
+    }
+
+    pub fn survives(&self, neighbours: usize) -> bool {
+        neighbours <= 8 && self.survive & (1 << neighbours) != 0
+    }
+
+    // Fewest neighbours a cell can survive with, deaths below it are from isolation
+    pub fn min_survival(&self) -> usize {
+        if self.survive == 0 {
+            9
+        } else {
+            self.survive.trailing_zeros() as usize
+        }
+    }
+}
+
+impl FromStr for LifeRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = |part: &str| -> anyhow::Result<u16> {
+            let mut mask = 0;
+            for c in part.chars() {
+                let n = c
+                    .to_digit(10)
+                    .with_context(|| format!("invalid digit '{c}'"))?;
+                if n > 8 {
+                    bail!("neighbour count {n} is out of range 0-8");
+                }
+                if mask & (1 << n) != 0 {
+                    bail!("neighbour count {n} is repeated");
+                }
+                mask |= 1 << n;
+            }
+            Ok(mask)
+        };
+
+        let upper = s.trim().to_ascii_uppercase();
+        let Some((birth, survive)) = upper.split_once('/') else {
+            bail!("expected a rule like B3/S23, got '{s}'");
+        };
+        let (birth, survive) = match (birth.strip_prefix('B'), survive.strip_prefix('S')) {
+            (Some(birth), Some(survive)) => (birth, survive),
+            // S/B order, e.g. S23/B3
+            _ => match (survive.strip_prefix('B'), birth.strip_prefix('S')) {
+                (Some(birth), Some(survive)) => (birth, survive),
+                // Plain survive/birth digits, e.g. 23/3
+                _ => (survive, birth),
+            },
+        };
+        Ok(Self {
+            birth: digits(birth)?,
+            survive: digits(survive)?,
+        })
+    }
+}
+
+impl fmt::Display for LifeRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "B")?;
+        for n in (0..=8).filter(|&n| self.births(n)) {
+            write!(f, "{n}")?;
+        }
+        write!(f, "/S")?;
+        for n in (0..=8).filter(|&n| self.survives(n)) {
+            write!(f, "{n}")?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for LifeRule {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<LifeRule> for String {
+    fn from(rule: LifeRule) -> Self {
+        rule.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_birth_and_survival_counts() {
+        let conway = LifeRule::CONWAY;
+        for rule in ["B3/S23", "b3/s23", " B3/S23 ", "S23/B3", "23/3"] {
+            assert_eq!(rule.parse::<LifeRule>().unwrap(), conway, "{rule}");
+        }
+        let seeds: LifeRule = "B2/S".parse().unwrap();
+        assert_eq!((seeds.birth, seeds.survive), (1 << 2, 0));
+        let highlife: LifeRule = "B36/S23".parse().unwrap();
+        assert!(highlife.births(6) && !highlife.births(2));
+        assert_eq!(highlife.to_string(), "B36/S23");
+        assert_eq!(
+            "B0/S012345678".parse::<LifeRule>().unwrap().min_survival(),
+            0
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_rules() {
+        for rule in [
+            "B9/S", "B3/S239", "B33/S23", "B3/S223", "B3S23", "", "B3/S23/2", "B3/x",
+        ] {
+            assert!(rule.parse::<LifeRule>().is_err(), "{rule}");
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use std::{f64::consts::TAU, fs, mem::discriminant, ops::Range, path::Path};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, Param};
+use crate::replay::{Entry, Event};
+
+// Sinusoidal swing of a parameter around its configured value
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Season {
+    // The parameter and how far it swings either way
+    pub amplitude: Param,
+    // Generations a full cycle takes
+    pub period: usize,
+    // Where in the cycle the run starts, as a fraction of it
+    #[serde(default)]
+    pub phase: f64,
+}
+
+// Parameter changes planned ahead of a run, loaded from a JSON file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schedule {
+    #[serde(default)]
+    pub seasons: Vec<Season>,
+    // Step changes and one-off events such as meteors, applied at the start
+    // of their generation like logged events
+    #[serde(default)]
+    pub events: Vec<Entry>,
+}
+
+impl Schedule {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read schedule {}", path.display()))?;
+        let schedule: Schedule = serde_json::from_str(&text)
+            .with_context(|| format!("failed to parse schedule {}", path.display()))?;
+        if schedule.seasons.iter().any(|season| season.period == 0) {
+            bail!("seasons need a period of at least one generation");
+        }
+        for entry in &schedule.events {
+            if let Event::Set(param) = entry.event {
+                param.check().with_context(|| {
+                    format!(
+                        "invalid event of generation {} in {}",
+                        entry.generation,
+                        path.display()
+                    )
+                })?;
+            }
+        }
+        Ok(schedule)
+    }
+
+    // `config` with every season at `generation` applied. Seasons of the same
+    // parameter add up before it is rounded.
+    pub fn season(&self, config: &Config, generation: usize) -> Config {
+        let mut swings: Vec<(Param, f64)> = Vec::new();
+        for season in &self.seasons {
+            let cycle = generation as f64 / season.period as f64 + season.phase;
+            let swing = season.amplitude.value() * (cycle * TAU).sin();
+            let param = season.amplitude;
+            match swings
+                .iter_mut()
+                .find(|(other, _)| discriminant(other) == discriminant(&param))
+            {
+                Some((_, total)) => *total += swing,
+                None => swings.push((param, swing)),
+            }
+        }
+        let mut seasonal = *config;
+        for (param, swing) in swings {
+            let base = param.current(config).value();
+            seasonal.set(param.with_value(base + swing));
+        }
+        seasonal
+    }
+
+    // Events scheduled for any of `generations`, in the order they are listed
+    pub fn events(&self, generations: Range<usize>) -> impl Iterator<Item = Event> + '_ {
+        self.events
+            .iter()
+            .filter(move |entry| generations.contains(&entry.generation))
+            .map(|entry| entry.event)
+    }
+}
+
+// The schedule used when none is loaded
+pub fn no_schedule() -> &'static Schedule {
+    static SCHEDULE: Schedule = Schedule {
+        seasons: Vec::new(),
+        events: Vec::new(),
+    };
+    &SCHEDULE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cell::Genome;
+    use crate::testing::temp_path;
+
+    // The example of the README
+    const SCHEDULE: &str = r#"{
+      "seasons": [
+        { "amplitude": { "reproduction": 1 }, "period": 200 },
+        { "amplitude": { "mutation": 0.005 }, "period": 200, "phase": 0.5 }
+      ],
+      "events": [
+        { "generation": 1000, "event": { "set": { "overpopulation": 5 } } },
+        { "generation": 2000, "event": { "meteor": { "x": 100, "y": 40, "radius": 20 } } }
+      ]
+    }"#;
+
+    fn load(text: &str) -> Result<Schedule> {
+        let path = temp_path("schedule.json");
+        fs::write(&path, text)?;
+        let schedule = Schedule::load(&path);
+        fs::remove_file(&path)?;
+        schedule
+    }
+
+    fn season(amplitude: Param, period: usize, phase: f64) -> Season {
+        Season {
+            amplitude,
+            period,
+            phase,
+        }
+    }
+
+    #[test]
+    fn loads_seasons_and_events() {
+        let schedule = load(SCHEDULE).unwrap();
+        assert_eq!(schedule.seasons.len(), 2);
+        assert_eq!(schedule.seasons[1].phase, 0.5);
+        assert_eq!(schedule.events(0..1000).count(), 0);
+        let events: Vec<_> = schedule.events(1000..2001).collect();
+        assert!(matches!(
+            events[..],
+            [Event::Set(Param::Overpopulation(5)), Event::Meteor { .. }]
+        ));
+
+        let json = serde_json::to_string(&schedule).unwrap();
+        let again: Schedule = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&again).unwrap(), json);
+    }
+
+    #[test]
+    fn rejects_seasons_without_a_period() {
+        let text = r#"{ "seasons": [{ "amplitude": { "age": 10 }, "period": 0 }] }"#;
+        assert!(load(text).is_err());
+        assert!(load(r#"{ "seasons": [], "weather": [] }"#).is_err());
+    }
+
+    #[test]
+    fn rejects_values_out_of_range() {
+        for (set, key) in [
+            (r#"{ "mutation": 5 }"#, "mutation"),
+            (r#"{ "reproduction_chance": -0.5 }"#, "reproduction_chance"),
+            (r#"{ "age": 0 }"#, "age"),
+        ] {
+            let text =
+                format!(r#"{{ "events": [{{ "generation": 3, "event": {{ "set": {set} }} }}] }}"#);
+            let error = format!("{:#}", load(&text).unwrap_err());
+            assert!(error.contains(key), "{error}");
+        }
+        let text = r#"{ "events": [{ "generation": 3, "event": { "set": { "mutation": 1 } } }] }"#;
+        assert!(load(text).is_ok());
+    }
+
+    #[test]
+    fn clamps_the_values_it_sets() {
+        let mut config = Config::default();
+        config.set(Param::Mutation(5.0));
+        config.set(Param::ReproductionChance(-1.0));
+        config.set(Param::Age(0));
+        assert_eq!(config.mutation, 1.0);
+        assert_eq!(config.reproduction_chance, 0.0);
+        assert_eq!(config.age, 1);
+        config.set(Param::Age(usize::MAX));
+        assert_eq!(config.age, Genome::MAX_AGE);
+    }
+
+    #[test]
+    fn swings_around_the_configured_value() {
+        let config = Config::default();
+        let schedule = Schedule {
+            seasons: vec![season(Param::Age(20), 100, 0.0)],
+            events: Vec::new(),
+        };
+        for (generation, age) in [(0, 100), (25, 120), (50, 100), (75, 80), (100, 100)] {
+            assert_eq!(schedule.season(&config, generation).age, age);
+        }
+    }
+
+    #[test]
+    fn stacked_seasons_add_up() {
+        let config = Config::default();
+        // Seven generations in each swings by 0.43, which alone rounds back to
+        // the configured 3 but together goes up to 4
+        let mut schedule = Schedule {
+            seasons: vec![season(Param::Reproduction(1), 100, 0.0)],
+            events: Vec::new(),
+        };
+        assert_eq!(schedule.season(&config, 7).reproduction, 3);
+        schedule.seasons.push(schedule.seasons[0]);
+        assert_eq!(schedule.season(&config, 7).reproduction, 4);
+
+        let schedule = Schedule {
+            seasons: vec![
+                season(Param::Mutation(0.01), 100, 0.25),
+                season(Param::Mutation(0.02), 100, 0.25),
+                season(Param::Age(10), 100, 0.25),
+            ],
+            events: Vec::new(),
+        };
+        let seasonal = schedule.season(&config, 0);
+        assert!((seasonal.mutation - (config.mutation + 0.03)).abs() < 1e-12);
+        assert_eq!(seasonal.age, config.age + 10);
+    }
+}
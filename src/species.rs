@@ -0,0 +1,423 @@
+use std::{fs, path::Path, sync::OnceLock};
+
+use anyhow::{bail, Context, Result};
+use crossterm::style::Color;
+use serde::{Deserialize, Serialize};
+
+use crate::cell::{Cell, CellState, CellType, Genome, Health};
+use crate::config::Config;
+
+// Species a cell has room for
+pub const MAX_SPECIES: usize = 16;
+
+// Colour of infected cells
+const INFECTED: u8 = 231;
+
+// A species and the rule parameters it sets for itself, the unset ones follow
+// the config and with it the keyboard controls
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Species {
+    pub name: String,
+    pub glyph: char,
+    // ANSI colours from young to old
+    pub colours: Vec<u8>,
+    // Weight of the species in the initial map. Unset, the first species gets
+    // 1 - `predetor_rate` and every other one `predetor_rate`.
+    #[serde(default)]
+    pub share: Option<f64>,
+    #[serde(default)]
+    pub reproduction: Option<usize>,
+    #[serde(default)]
+    pub reproduction_chance: Option<f64>,
+    #[serde(default)]
+    pub overpopulation: Option<usize>,
+    #[serde(default)]
+    pub underpopulation: Option<usize>,
+    #[serde(default)]
+    pub survivability: Option<usize>,
+    #[serde(default)]
+    pub age: Option<usize>,
+    #[serde(default)]
+    pub aging_rate: Option<usize>,
+    #[serde(default)]
+    pub resistence: Option<usize>,
+    #[serde(default)]
+    pub energy_loss: Option<usize>,
+    #[serde(default)]
+    pub energy_gain: Option<usize>,
+    #[serde(default)]
+    pub breed_energy: Option<usize>,
+    #[serde(default)]
+    pub birth_energy: Option<usize>,
+}
+
+impl Species {
+    fn new(name: &str, glyph: char, colours: [u8; 4]) -> Self {
+        Self {
+            name: name.to_string(),
+            glyph,
+            colours: colours.to_vec(),
+            share: None,
+            reproduction: None,
+            reproduction_chance: None,
+            overpopulation: None,
+            underpopulation: None,
+            survivability: None,
+            age: None,
+            aging_rate: None,
+            resistence: None,
+            energy_loss: None,
+            energy_gain: None,
+            breed_energy: None,
+            birth_energy: None,
+        }
+    }
+}
+
+// `predator` eats `prey` once `resistence` more of them than the prey's own
+// inherited resistence are around it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Diet {
+    pub predator: String,
+    pub prey: String,
+    #[serde(default)]
+    pub resistence: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FoodWebFile {
+    species: Vec<Species>,
+    #[serde(default)]
+    food_web: Vec<Diet>,
+}
+
+// The species of a run and who eats whom. Species that eat others live off
+// energy and hunt, the others breed from the cells around them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "FoodWebFile", into = "FoodWebFile")]
+pub struct FoodWeb {
+    species: Vec<Species>,
+    diets: Vec<Diet>,
+    // Resistence of each predator and prey pair that is in the web
+    resistence: [[Option<usize>; MAX_SPECIES]; MAX_SPECIES],
+}
+
+impl TryFrom<FoodWebFile> for FoodWeb {
+    type Error = anyhow::Error;
+
+    fn try_from(file: FoodWebFile) -> Result<Self> {
+        if file.species.is_empty() || file.species.len() > MAX_SPECIES {
+            bail!(
+                "expected 1 to {MAX_SPECIES} species, got {}",
+                file.species.len()
+            );
+        }
+        let index = |name: &str| {
+            file.species
+                .iter()
+                .position(|species| species.name == name)
+                .with_context(|| format!("unknown species '{name}' in the food web"))
+        };
+        for (i, species) in file.species.iter().enumerate() {
+            if index(&species.name)? != i {
+                bail!("species '{}' is defined twice", species.name);
+            }
+            if species.colours.is_empty() {
+                bail!("species '{}' needs at least one colour", species.name);
+            }
+            if species
+                .reproduction_chance
+                .is_some_and(|chance| !(0.0..=1.0).contains(&chance))
+            {
+                bail!(
+                    "species '{}' needs a reproduction chance from 0 to 1",
+                    species.name
+                );
+            }
+            if species.age.is_some_and(|age| age > Genome::MAX_AGE) {
+                bail!(
+                    "species '{}' can't live longer than {} generations",
+                    species.name,
+                    Genome::MAX_AGE
+                );
+            }
+        }
+        let mut resistence = [[None; MAX_SPECIES]; MAX_SPECIES];
+        for diet in &file.food_web {
+            resistence[index(&diet.predator)?][index(&diet.prey)?] = Some(diet.resistence);
+        }
+        Ok(Self {
+            species: file.species,
+            diets: file.food_web,
+            resistence,
+        })
+    }
+}
+
+impl From<FoodWeb> for FoodWebFile {
+    fn from(web: FoodWeb) -> Self {
+        Self {
+            species: web.species,
+            food_web: web.diets,
+        }
+    }
+}
+
+// Prey and the predators that eat them
+impl Default for FoodWeb {
+    fn default() -> Self {
+        FoodWeb::try_from(FoodWebFile {
+            species: vec![
+                // Green, yellow, orange and red
+                Species::new("prey", '◈', [47, 226, 214, 196]),
+                // Cyan, violet, magenta and blue
+                Species::new("predator", '¤', [81, 135, 201, 21]),
+            ],
+            food_web: vec![Diet {
+                predator: "predator".to_string(),
+                prey: "prey".to_string(),
+                resistence: 0,
+            }],
+        })
+        .expect("the default food web is valid")
+    }
+}
+
+impl FoodWeb {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read food web {}", path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("failed to parse food web {}", path.display()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.species.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.species.is_empty()
+    }
+
+    pub fn kinds(&self) -> impl Iterator<Item = CellType> {
+        (0..self.species.len() as u8).map(CellType)
+    }
+
+    pub fn name(&self, kind: CellType) -> &str {
+        &self.species[kind.index()].name
+    }
+
+    // How many more of `predator` a prey of `prey` resists, None if it doesn't eat them
+    pub fn resistence(&self, predator: CellType, prey: CellType) -> Option<usize> {
+        self.resistence[predator.index()][prey.index()]
+    }
+
+    // Parameters of every species, falling back to `config`
+    pub fn rules(&self, config: &Config) -> Vec<Rules> {
+        self.kinds()
+            .map(|kind| {
+                let species = &self.species[kind.index()];
+                let mut food = 0;
+                let mut eaters = 0;
+                for other in self.kinds() {
+                    if self.resistence(kind, other).is_some() {
+                        food |= 1 << other.0;
+                    }
+                    if self.resistence(other, kind).is_some() {
+                        eaters |= 1 << other.0;
+                    }
+                }
+                Rules {
+                    reproduction: species.reproduction.unwrap_or(config.reproduction),
+                    reproduction_chance: species
+                        .reproduction_chance
+                        .unwrap_or(config.reproduction_chance),
+                    overpopulation: species.overpopulation.unwrap_or(config.overpopulation),
+                    underpopulation: species.underpopulation.unwrap_or(config.underpopulation),
+                    survivability: species.survivability.unwrap_or(config.survivability),
+                    age: species.age.unwrap_or(config.age),
+                    aging_rate: species.aging_rate.unwrap_or(config.aging_rate),
+                    resistence: species.resistence.unwrap_or(config.resistence),
+                    energy_loss: species.energy_loss.unwrap_or(config.energy_loss),
+                    energy_gain: species.energy_gain.unwrap_or(config.energy_gain),
+                    breed_energy: species.breed_energy.unwrap_or(config.breed_energy),
+                    birth_energy: species.birth_energy.unwrap_or(config.birth_energy),
+                    food,
+                    eaters,
+                }
+            })
+            .collect()
+    }
+
+    // Weights of the species in the initial map
+    pub fn shares(&self, predetor_rate: f64) -> Vec<f64> {
+        self.species
+            .iter()
+            .enumerate()
+            .map(|(i, species)| {
+                species.share.unwrap_or(if i == 0 {
+                    1.0 - predetor_rate
+                } else {
+                    predetor_rate
+                })
+            })
+            .collect()
+    }
+
+    pub fn glyph(&self, cell: Cell) -> char {
+        match cell.state() {
+            CellState::Alive { .. } => self.species[cell.kind().index()].glyph,
+            CellState::Dead => ' ',
+        }
+    }
+
+    // The species' colours are spread evenly over its lifespan
+    pub fn color(&self, cell: Cell, max_age: usize) -> Color {
+        match cell.state() {
+            // Infected cells of every species stand out in white
+            CellState::Alive {
+                health: Health::Infected,
+                ..
+            } => Color::AnsiValue(INFECTED),
+            CellState::Alive { age, .. } => {
+                let colours = &self.species[cell.kind().index()].colours;
+                let live = age as f64 / max_age as f64;
+                let shade = ((live * colours.len() as f64) as usize).min(colours.len() - 1);
+                Color::AnsiValue(colours[shade])
+            }
+            CellState::Dead => Color::Black,
+        }
+    }
+}
+
+// The web used when none is loaded
+pub fn default_web() -> &'static FoodWeb {
+    static WEB: OnceLock<FoodWeb> = OnceLock::new();
+    WEB.get_or_init(FoodWeb::default)
+}
+
+// Parameters of a species for one step
+#[derive(Debug, Clone, Copy)]
+pub struct Rules {
+    pub reproduction: usize,
+    pub reproduction_chance: f64,
+    pub overpopulation: usize,
+    pub underpopulation: usize,
+    pub survivability: usize,
+    pub age: usize,
+    pub aging_rate: usize,
+    pub resistence: usize,
+    pub energy_loss: usize,
+    pub energy_gain: usize,
+    pub breed_energy: usize,
+    pub birth_energy: usize,
+    // Species it eats and species that eat it, one bit per species
+    pub food: u16,
+    pub eaters: u16,
+}
+
+impl Rules {
+    // Species that eat others hunt and live off energy
+    pub fn is_predator(&self) -> bool {
+        self.food != 0
+    }
+
+    // Genome of cells without parents, i.e. the initial, mutated and spawned ones
+    pub fn genome(&self) -> Genome {
+        Genome::new(
+            self.age,
+            self.reproduction_chance,
+            self.resistence,
+            self.aging_rate,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_path;
+
+    // The example of the README
+    const WEB: &str = r#"{
+      "species": [
+        { "name": "grass", "glyph": "░", "colours": [22, 28, 34], "share": 0.8, "resistence": 0 },
+        { "name": "rabbit", "glyph": "◈", "colours": [230, 223, 180], "share": 0.15, "resistence": 0, "underpopulation": 0 },
+        { "name": "fox", "glyph": "¤", "colours": [208, 202, 166], "share": 0.05, "underpopulation": 0, "breed_energy": 20 }
+      ],
+      "food_web": [
+        { "predator": "rabbit", "prey": "grass" },
+        { "predator": "fox", "prey": "rabbit", "resistence": 1 }
+      ]
+    }"#;
+
+    fn parse(text: &str) -> Result<FoodWeb> {
+        Ok(serde_json::from_str(text)?)
+    }
+
+    #[test]
+    fn loads_species_and_diets() {
+        let path = temp_path("web.json");
+        fs::write(&path, WEB).unwrap();
+        let web = FoodWeb::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let [grass, rabbit, fox] = [CellType(0), CellType(1), CellType(2)];
+        assert_eq!(web.len(), 3);
+        assert_eq!(web.name(fox), "fox");
+        assert_eq!(web.resistence(rabbit, grass), Some(0));
+        assert_eq!(web.resistence(fox, rabbit), Some(1));
+        assert_eq!(web.resistence(fox, grass), None);
+        assert_eq!(web.shares(0.5), [0.8, 0.15, 0.05]);
+
+        // Unset parameters follow the config
+        let config = Config::default();
+        let rules = web.rules(&config);
+        assert!(!rules[grass.index()].is_predator());
+        assert!(rules[rabbit.index()].is_predator() && rules[fox.index()].is_predator());
+        assert_eq!(rules[rabbit.index()].food, 1 << grass.0);
+        assert_eq!(rules[rabbit.index()].eaters, 1 << fox.0);
+        assert_eq!(rules[fox.index()].breed_energy, 20);
+        assert_eq!(rules[rabbit.index()].breed_energy, config.breed_energy);
+        assert_eq!(rules[fox.index()].underpopulation, 0);
+        assert_eq!(rules[grass.index()].underpopulation, config.underpopulation);
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        for web in [parse(WEB).unwrap(), FoodWeb::default()] {
+            let json = serde_json::to_string(&web).unwrap();
+            let again = parse(&json).unwrap();
+            assert_eq!(serde_json::to_string(&again).unwrap(), json);
+            assert_eq!(again.len(), web.len());
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_webs() {
+        let species =
+            |name: &str| format!(r#"{{ "name": "{name}", "glyph": "x", "colours": [1] }}"#);
+        let too_many: Vec<_> = (0..=MAX_SPECIES).map(|i| species(&i.to_string())).collect();
+        for text in [
+            r#"{ "species": [] }"#.to_string(),
+            format!(r#"{{ "species": [{}] }}"#, too_many.join(",")),
+            format!(r#"{{ "species": [{}, {}] }}"#, species("a"), species("a")),
+            r#"{ "species": [{ "name": "a", "glyph": "x", "colours": [] }] }"#.to_string(),
+            r#"{ "species": [{ "name": "a", "glyph": "x", "colours": [1], "age": 5000 }] }"#
+                .to_string(),
+            r#"{ "species": [{ "name": "a", "glyph": "x", "colours": [1], "reproduction_chance": 2 }] }"#
+                .to_string(),
+            r#"{ "species": [{ "name": "a", "glyph": "x", "colours": [1], "speed": 2 }] }"#
+                .to_string(),
+            format!(
+                r#"{{ "species": [{}], "food_web": [{{ "predator": "a", "prey": "b" }}] }}"#,
+                species("a")
+            ),
+        ] {
+            assert!(parse(&text).is_err(), "{text}");
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::cell::{Cell, CellState, CellType, Health};
+use crate::species::MAX_SPECIES;
+
+// What happened to a single cell between two generations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    Born,
+    Mutated,
+    AgedOut,
+    Eaten,
+    Starved,
+    Overcrowded,
+    Isolated,
+    Survived,
+    Infected,
+    Recovered,
+    Diseased,
+}
+
+impl Transition {
+    pub const ALL: [Transition; 11] = [
+        Transition::Born,
+        Transition::Mutated,
+        Transition::AgedOut,
+        Transition::Eaten,
+        Transition::Starved,
+        Transition::Overcrowded,
+        Transition::Isolated,
+        Transition::Survived,
+        Transition::Infected,
+        Transition::Recovered,
+        Transition::Diseased,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Transition::Born => "born",
+            Transition::Mutated => "mutated",
+            Transition::AgedOut => "aged_out",
+            Transition::Eaten => "eaten",
+            Transition::Starved => "starved",
+            Transition::Overcrowded => "overcrowded",
+            Transition::Isolated => "isolated",
+            Transition::Survived => "survived",
+            Transition::Infected => "infected",
+            Transition::Recovered => "recovered",
+            Transition::Diseased => "diseased",
+        }
+    }
+
+    // Only happens while the epidemic is on
+    pub fn is_disease(&self) -> bool {
+        matches!(
+            self,
+            Transition::Infected | Transition::Recovered | Transition::Diseased
+        )
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+// Per generation tally of transitions, split by species
+#[derive(Debug, Clone, Copy)]
+pub struct Census {
+    counts: [[usize; Transition::ALL.len()]; MAX_SPECIES],
+}
+
+impl Default for Census {
+    fn default() -> Self {
+        Self {
+            counts: [[0; Transition::ALL.len()]; MAX_SPECIES],
+        }
+    }
+}
+
+impl Census {
+    pub fn record(&mut self, kind: CellType, transition: Transition) {
+        self.add(kind, transition, 1);
+    }
+
+    pub fn add(&mut self, kind: CellType, transition: Transition, count: usize) {
+        self.counts[kind.index()][transition.index()] += count;
+    }
+
+    pub fn merge(&mut self, other: &Census) {
+        for (counts, other) in self.counts.iter_mut().zip(&other.counts) {
+            for (count, other) in counts.iter_mut().zip(other) {
+                *count += other;
+            }
+        }
+    }
+
+    pub fn get(&self, kind: CellType, transition: Transition) -> usize {
+        self.counts[kind.index()][transition.index()]
+    }
+
+    // Count of each transition of `kind` by label, leaving out the epidemic
+    // unless `disease` is on
+    pub fn counts(&self, kind: CellType, disease: bool) -> BTreeMap<&'static str, usize> {
+        Transition::ALL
+            .into_iter()
+            .filter(|transition| disease || !transition.is_disease())
+            .map(|transition| (transition.label(), self.get(kind, transition)))
+            .collect()
+    }
+
+    // One line per transition with the count of each of the first `species`,
+    // leaving out the epidemic unless `disease` is on
+    pub fn summary(&self, species: usize, disease: bool) -> String {
+        let mut summary = String::new();
+        for transition in Transition::ALL {
+            if transition.is_disease() && !disease {
+                continue;
+            }
+            let counts: Vec<_> = (0..species as u8)
+                .map(|kind| self.get(CellType(kind), transition).to_string())
+                .collect();
+            summary.push_str(&format!("{}: {}\n", transition.label(), counts.join(" / ")));
+        }
+        summary
+    }
+}
+
+// Mean heritable traits of the live cells of a species
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Traits {
+    pub max_age: f64,
+    pub reproduction: f64,
+    pub resistence: f64,
+    pub aging_rate: f64,
+}
+
+// Trait totals of the live cells of each species
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GenePool {
+    species: [(usize, Traits); MAX_SPECIES],
+}
+
+impl GenePool {
+    pub fn add(&mut self, cell: Cell) {
+        if !cell.is_alive() {
+            return;
+        }
+        let (count, sum) = &mut self.species[cell.kind().index()];
+        let genome = cell.genome();
+        *count += 1;
+        sum.max_age += genome.max_age as f64;
+        sum.reproduction += genome.reproduction_chance();
+        sum.resistence += genome.resistence as f64;
+        sum.aging_rate += genome.aging_rate as f64;
+    }
+
+    // Zero for a species without live cells
+    pub fn mean(&self, kind: CellType) -> Traits {
+        let (count, sum) = self.species[kind.index()];
+        let n = count.max(1) as f64;
+        Traits {
+            max_age: sum.max_age / n,
+            reproduction: sum.reproduction / n,
+            resistence: sum.resistence / n,
+            aging_rate: sum.aging_rate / n,
+        }
+    }
+
+    // One line per trait with the mean of each of the first `species`
+    pub fn summary(&self, species: usize) -> String {
+        let means: Vec<_> = (0..species as u8)
+            .map(|kind| self.mean(CellType(kind)))
+            .collect();
+        let line = |label: &str, value: fn(&Traits) -> String| {
+            let values: Vec<_> = means.iter().map(value).collect();
+            format!("{label}: {}\n", values.join(" / "))
+        };
+        line("max_age", |t| format!("{:.1}", t.max_age))
+            + &line("reproduction", |t| format!("{:.3}", t.reproduction))
+            + &line("resistence", |t| format!("{:.2}", t.resistence))
+            + &line("aging_rate", |t| format!("{:.2}", t.aging_rate))
+    }
+}
+
+// Infected and recovered live cells of a species
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Outbreak {
+    pub infected: usize,
+    pub recovered: usize,
+}
+
+// Epidemic tally of the live cells of each species
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Epidemic {
+    species: [Outbreak; MAX_SPECIES],
+}
+
+impl Epidemic {
+    pub fn add(&mut self, cell: Cell) {
+        let outbreak = &mut self.species[cell.kind().index()];
+        match cell.state() {
+            CellState::Alive {
+                health: Health::Infected,
+                ..
+            } => outbreak.infected += 1,
+            CellState::Alive {
+                health: Health::Recovered,
+                ..
+            } => outbreak.recovered += 1,
+            _ => {}
+        }
+    }
+
+    pub fn get(&self, kind: CellType) -> Outbreak {
+        self.species[kind.index()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn census_counts_each_species_apart() {
+        let (prey, predator) = (CellType::PREY, CellType::PREDETOR);
+        let mut census = Census::default();
+        census.record(prey, Transition::Born);
+        census.record(prey, Transition::Born);
+        census.add(predator, Transition::Starved, 3);
+        census.record(predator, Transition::Infected);
+
+        let mut other = Census::default();
+        other.record(prey, Transition::Born);
+        other.record(predator, Transition::Eaten);
+        census.merge(&other);
+
+        assert_eq!(census.get(prey, Transition::Born), 3);
+        assert_eq!(census.get(predator, Transition::Born), 0);
+        assert_eq!(census.get(predator, Transition::Starved), 3);
+        assert_eq!(census.get(predator, Transition::Eaten), 1);
+
+        let counts = census.counts(predator, false);
+        assert_eq!(counts["starved"], 3);
+        assert!(!counts.contains_key("infected"));
+        assert_eq!(census.counts(predator, true)["infected"], 1);
+
+        let summary = census.summary(2, false);
+        assert!(summary.contains("born: 3 / 0\n"));
+        assert!(summary.contains("starved: 0 / 3\n"));
+        assert!(!summary.contains("infected"));
+    }
+}
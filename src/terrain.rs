@@ -0,0 +1,334 @@
+use std::{fs, io::BufReader, path::Path};
+
+use anyhow::{bail, Context, Result};
+use crossterm::style::Color;
+use serde::{Deserialize, Serialize};
+
+// What lies under a cell of the map
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Ground {
+    #[default]
+    Open,
+    // Never alive and nothing moves into it, and it hides the cells behind it
+    // from any neighbourhood
+    Wall,
+    // Water or rock where nothing is born
+    Barren,
+    // Prey breed more often, see `Config::fertility`
+    Fertile,
+    // Nothing living here can be caught
+    Refuge,
+}
+
+impl Ground {
+    // Text maps use one character per cell, anything else is open ground
+    const SYMBOLS: [(char, Ground); 4] = [
+        ('#', Ground::Wall),
+        ('~', Ground::Barren),
+        ('*', Ground::Fertile),
+        ('^', Ground::Refuge),
+    ];
+
+    // Image maps use the nearest of these colours
+    const COLOURS: [([u8; 3], Ground); 5] = [
+        ([255, 255, 255], Ground::Open),
+        ([0, 0, 0], Ground::Wall),
+        ([0, 0, 255], Ground::Barren),
+        ([0, 255, 0], Ground::Fertile),
+        ([255, 255, 0], Ground::Refuge),
+    ];
+
+    fn from_symbol(symbol: char) -> Ground {
+        Ground::SYMBOLS
+            .iter()
+            .find(|(s, _)| *s == symbol)
+            .map_or(Ground::Open, |&(_, ground)| ground)
+    }
+
+    fn symbol(self) -> char {
+        Ground::SYMBOLS
+            .iter()
+            .find(|(_, g)| *g == self)
+            .map_or('.', |&(symbol, _)| symbol)
+    }
+
+    fn from_rgb(rgb: [u8; 3]) -> Ground {
+        let distance = |colour: &[u8; 3]| {
+            colour
+                .iter()
+                .zip(rgb)
+                .map(|(&a, b)| (a as i32 - b as i32).pow(2))
+                .sum::<i32>()
+        };
+        Ground::COLOURS
+            .iter()
+            .min_by_key(|(colour, _)| distance(colour))
+            .map_or(Ground::Open, |&(_, ground)| ground)
+    }
+
+    pub fn allows_births(self) -> bool {
+        !matches!(self, Ground::Wall | Ground::Barren)
+    }
+
+    // Background under an empty cell, None for open ground
+    pub fn color(self) -> Option<Color> {
+        match self {
+            Ground::Open => None,
+            Ground::Wall => Some(Color::AnsiValue(244)),
+            Ground::Barren => Some(Color::AnsiValue(17)),
+            Ground::Fertile => Some(Color::AnsiValue(58)),
+            Ground::Refuge => Some(Color::AnsiValue(53)),
+        }
+    }
+}
+
+// Static ground of the map, loaded from a text or PNG map. Serialized as rows
+// of the text map's characters so logs replay on the same terrain.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(try_from = "Vec<String>", into = "Vec<String>")]
+pub struct Terrain {
+    w: usize,
+    h: usize,
+    ground: Vec<Ground>,
+    // Summed-area table of the walls, to tell cheaply whether any are near
+    walls: Vec<u32>,
+}
+
+impl TryFrom<Vec<String>> for Terrain {
+    type Error = anyhow::Error;
+
+    fn try_from(rows: Vec<String>) -> Result<Self> {
+        let w = rows
+            .iter()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or(0);
+        let h = rows.len();
+        let mut ground = vec![Ground::Open; w * h];
+        for (y, row) in rows.iter().enumerate() {
+            for (x, symbol) in row.chars().enumerate() {
+                ground[y * w + x] = Ground::from_symbol(symbol);
+            }
+        }
+        Ok(Self::new(w, h, ground))
+    }
+}
+
+impl From<Terrain> for Vec<String> {
+    fn from(terrain: Terrain) -> Self {
+        terrain
+            .ground
+            .chunks(terrain.w.max(1))
+            .map(|row| row.iter().map(|ground| ground.symbol()).collect())
+            .collect()
+    }
+}
+
+impl Terrain {
+    fn new(w: usize, h: usize, ground: Vec<Ground>) -> Self {
+        let mut walls = vec![0; (w + 1) * (h + 1)];
+        for y in 0..h {
+            for x in 0..w {
+                let wall = (ground[y * w + x] == Ground::Wall) as u32;
+                walls[(y + 1) * (w + 1) + x + 1] =
+                    wall + walls[y * (w + 1) + x + 1] + walls[(y + 1) * (w + 1) + x]
+                        - walls[y * (w + 1) + x];
+            }
+        }
+        Self {
+            w,
+            h,
+            ground,
+            walls,
+        }
+    }
+
+    // PNG files are read as images, anything else as a text map
+    pub fn load(path: &Path) -> Result<Self> {
+        let terrain = if path.extension().is_some_and(|ext| ext == "png") {
+            Terrain::load_png(path)
+        } else {
+            let text = fs::read_to_string(path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            Terrain::try_from(text.lines().map(str::to_string).collect::<Vec<_>>())
+        }
+        .with_context(|| format!("failed to load terrain {}", path.display()))?;
+        if terrain.ground.is_empty() {
+            bail!("terrain {} is empty", path.display());
+        }
+        Ok(terrain)
+    }
+
+    fn load_png(path: &Path) -> Result<Self> {
+        let file = fs::File::open(path)?;
+        let mut decoder = png::Decoder::new(BufReader::new(file));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut buf = vec![0; reader.output_buffer_size().context("image too large")?];
+        let info = reader.next_frame(&mut buf)?;
+        let (w, h) = (info.width as usize, info.height as usize);
+        let samples = info.color_type.samples();
+        let ground = buf[..info.buffer_size()]
+            .chunks_exact(info.line_size)
+            .flat_map(|line| line[..w * samples].chunks_exact(samples))
+            .map(|pixel| match pixel {
+                [grey] | [grey, _] => Ground::from_rgb([*grey; 3]),
+                [r, g, b, ..] => Ground::from_rgb([*r, *g, *b]),
+                _ => Ground::Open,
+            })
+            .collect();
+        Ok(Self::new(w, h, ground))
+    }
+
+    // Stretched or shrunk to `w` by `h` cells
+    pub fn resize(&self, w: usize, h: usize) -> Terrain {
+        let mut ground = Vec::with_capacity(w * h);
+        for y in 0..h {
+            for x in 0..w {
+                ground.push(self.ground[y * self.h / h * self.w + x * self.w / w]);
+            }
+        }
+        Terrain::new(w, h, ground)
+    }
+
+    // Open outside the map, so runs without terrain are open everywhere
+    pub fn get(&self, x: usize, y: usize) -> Ground {
+        if x < self.w && y < self.h {
+            self.ground[y * self.w + x]
+        } else {
+            Ground::Open
+        }
+    }
+
+    // Whether there are walls within `radius` of (x, y), on either axis
+    pub fn walls_near(&self, x: usize, y: usize, radius: usize) -> bool {
+        if self.walls.is_empty() || x >= self.w || y >= self.h {
+            return false;
+        }
+        let (left, top) = (x.saturating_sub(radius), y.saturating_sub(radius));
+        let right = (x + radius + 1).min(self.w);
+        let bottom = (y + radius + 1).min(self.h);
+        let at = |x: usize, y: usize| self.walls[y * (self.w + 1) + x];
+        at(right, bottom) + at(left, top) - at(left, bottom) - at(right, top) > 0
+    }
+
+    // Whether a wall stands on the straight line from (x, y) to the cell `dx`,
+    // `dy` away, between the two. The line goes through every row and column
+    // in between, so a wall across it can't be seen past.
+    pub fn blocks(&self, x: usize, y: usize, dx: isize, dy: isize) -> bool {
+        let steps = dx.abs().max(dy.abs());
+        // Rounded to the nearest cell
+        let along = |d: isize, i: isize| (2 * d * i + steps).div_euclid(2 * steps);
+        (1..steps).any(|i| {
+            let cx = x.wrapping_add(along(dx, i) as usize);
+            let cy = y.wrapping_add(along(dy, i) as usize);
+            self.get(cx, cy) == Ground::Wall
+        })
+    }
+}
+
+// The terrain used when none is loaded
+pub fn open_terrain() -> &'static Terrain {
+    static TERRAIN: Terrain = Terrain {
+        w: 0,
+        h: 0,
+        ground: Vec::new(),
+        walls: Vec::new(),
+    };
+    &TERRAIN
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_path;
+
+    fn rows(terrain: &Terrain) -> Vec<String> {
+        terrain.clone().into()
+    }
+
+    #[test]
+    fn loads_text_maps() {
+        let path = temp_path("terrain.txt");
+        fs::write(&path, "#~*^.\r\nx#\n").unwrap();
+        let terrain = Terrain::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let ground = [
+            Ground::Wall,
+            Ground::Barren,
+            Ground::Fertile,
+            Ground::Refuge,
+            Ground::Open,
+        ];
+        for (x, ground) in ground.into_iter().enumerate() {
+            assert_eq!(terrain.get(x, 0), ground);
+        }
+        // Short rows are open to the end, and so is everything off the map
+        assert_eq!(rows(&terrain), ["#~*^.", ".#..."]);
+        assert_eq!(terrain.get(5, 0), Ground::Open);
+        assert_eq!(terrain.get(0, 2), Ground::Open);
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let terrain = Terrain::try_from(vec!["#~".to_string(), "*^".to_string()]).unwrap();
+        let json = serde_json::to_string(&terrain).unwrap();
+        assert_eq!(json, r##"["#~","*^"]"##);
+        let again: Terrain = serde_json::from_str(&json).unwrap();
+        assert_eq!(rows(&again), rows(&terrain));
+    }
+
+    #[test]
+    fn stretches_and_shrinks() {
+        let terrain = Terrain::try_from(vec!["#~".to_string(), "*^".to_string()]).unwrap();
+        assert_eq!(rows(&terrain.resize(4, 3)), ["##~~", "##~~", "**^^"]);
+        let big = terrain.resize(6, 6);
+        assert_eq!(rows(&big.resize(2, 2)), rows(&terrain));
+    }
+
+    #[test]
+    fn loads_png_maps_by_the_nearest_colour() {
+        let path = temp_path("terrain.png");
+        let pixels: [[u8; 3]; 5] = [
+            [250, 250, 250],
+            [10, 10, 10],
+            [20, 0, 200],
+            [0, 220, 30],
+            [240, 240, 0],
+        ];
+        {
+            let file = fs::File::create(&path).unwrap();
+            let mut encoder = png::Encoder::new(file, pixels.len() as u32, 1);
+            encoder.set_color(png::ColorType::Rgb);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(pixels.as_flattened()).unwrap();
+        }
+        let terrain = Terrain::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(rows(&terrain), [".#~*^"]);
+    }
+
+    #[test]
+    fn walls_block_the_lines_across_them() {
+        let rows = ["..#..", ".....", "....."].map(str::to_string).to_vec();
+        let terrain = Terrain::try_from(rows).unwrap();
+        assert!(terrain.walls_near(0, 2, 2));
+        assert!(!terrain.walls_near(0, 2, 1));
+        assert!(!open_terrain().walls_near(0, 0, 5));
+
+        assert!(terrain.blocks(1, 0, 2, 0));
+        assert!(terrain.blocks(1, 1, 2, -2));
+        assert!(!terrain.blocks(1, 0, 1, 0));
+        assert!(!terrain.blocks(1, 1, 2, 0));
+        assert!(!terrain.blocks(4, 0, 0, 2));
+    }
+
+    #[test]
+    fn rejects_empty_maps() {
+        let path = temp_path("empty.txt");
+        fs::write(&path, "").unwrap();
+        assert!(Terrain::load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
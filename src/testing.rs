@@ -0,0 +1,6 @@
+use std::path::PathBuf;
+
+// A path in the temp directory unique to this test process
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("gameoflife-{}-{name}", std::process::id()))
+}
@@ -0,0 +1,151 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::cell::Cell;
+use crate::grid::Grid;
+use crate::species::MAX_SPECIES;
+
+// Side length of a tile
+pub const TILE: usize = 64;
+
+pub type TileKey = (i64, i64);
+
+// Unbounded plane stored as square tiles keyed by their position in tiles.
+// Only tiles holding a live cell are kept, everything else is dead.
+#[derive(Debug, Clone, Default)]
+pub struct World {
+    tiles: HashMap<TileKey, Grid<Cell>>,
+}
+
+impl World {
+    pub fn get(&self, x: i64, y: i64) -> Cell {
+        let (key, lx, ly) = split(x, y);
+        self.tiles
+            .get(&key)
+            .map_or(Cell::default(), |tile| tile[(lx, ly)])
+    }
+
+    pub fn set(&mut self, x: i64, y: i64, cell: Cell) {
+        let (key, lx, ly) = split(x, y);
+        if let Some(tile) = self.tiles.get_mut(&key) {
+            tile[(lx, ly)] = cell;
+        } else if cell.is_alive() {
+            let tile = self
+                .tiles
+                .entry(key)
+                .or_insert_with(|| Grid::new(TILE, TILE, Cell::default()));
+            tile[(lx, ly)] = cell;
+        }
+    }
+
+    // Keeps `tile` only if something lives in it
+    pub fn insert(&mut self, key: TileKey, tile: Grid<Cell>) {
+        if tile.iter().any(Cell::is_alive) {
+            self.tiles.insert(key, tile);
+        } else {
+            self.tiles.remove(&key);
+        }
+    }
+
+    pub fn contains(&self, key: TileKey) -> bool {
+        self.tiles.contains_key(&key)
+    }
+
+    pub fn tiles(&self) -> usize {
+        self.tiles.len()
+    }
+
+    // Stored tiles and every tile within `reach` tiles of them, in a fixed order
+    pub fn active(&self, reach: i64) -> Vec<TileKey> {
+        let mut active = HashSet::new();
+        for &(tx, ty) in self.tiles.keys() {
+            for dy in -reach..=reach {
+                for dx in -reach..=reach {
+                    active.insert((tx + dx, ty + dy));
+                }
+            }
+        }
+        let mut active: Vec<_> = active.into_iter().collect();
+        active.sort_unstable();
+        active
+    }
+
+    // Copies the cells with their top left corner at (x0, y0) into `out`
+    pub fn copy_region(&self, x0: i64, y0: i64, out: &mut Grid<Cell>) {
+        let (w, h) = (out.width() as i64, out.height() as i64);
+        let size = TILE as i64;
+        for ty in y0.div_euclid(size)..=(y0 + h - 1).div_euclid(size) {
+            for tx in x0.div_euclid(size)..=(x0 + w - 1).div_euclid(size) {
+                let Some(tile) = self.tiles.get(&(tx, ty)) else {
+                    continue;
+                };
+                let (left, top) = (tx * size, ty * size);
+                let xs = left.max(x0)..(left + size).min(x0 + w);
+                for y in top.max(y0)..(top + size).min(y0 + h) {
+                    for x in xs.clone() {
+                        out[((x - x0) as usize, (y - y0) as usize)] =
+                            tile[((x - left) as usize, (y - top) as usize)];
+                    }
+                }
+            }
+        }
+    }
+
+    // Position and cell of everything alive, tile by tile in a fixed order
+    pub fn live_cells(&self) -> Vec<((i64, i64), Cell)> {
+        let mut keys: Vec<_> = self.tiles.keys().copied().collect();
+        keys.sort_unstable();
+        let mut cells = Vec::new();
+        for (tx, ty) in keys {
+            for (y, row) in self.tiles[&(tx, ty)].rows().enumerate() {
+                for (x, cell) in row.iter().enumerate() {
+                    if cell.is_alive() {
+                        let pos = (tx * TILE as i64 + x as i64, ty * TILE as i64 + y as i64);
+                        cells.push((pos, *cell));
+                    }
+                }
+            }
+        }
+        cells
+    }
+
+    // Every cell of the stored tiles, dead or alive, in no particular order
+    pub fn cells(&self) -> impl Iterator<Item = &Cell> {
+        self.tiles.values().flat_map(Grid::iter)
+    }
+
+    // Live cells of each species
+    pub fn population(&self) -> [usize; MAX_SPECIES] {
+        let mut population = [0; MAX_SPECIES];
+        for cell in self.cells().filter(|cell| cell.is_alive()) {
+            population[cell.kind().index()] += 1;
+        }
+        population
+    }
+
+    // Mean position of every live cell
+    pub fn centroid(&self) -> Option<(i64, i64)> {
+        let (mut sx, mut sy, mut n) = (0i128, 0i128, 0i128);
+        for (&(tx, ty), tile) in &self.tiles {
+            for (y, row) in tile.rows().enumerate() {
+                for (x, cell) in row.iter().enumerate() {
+                    if cell.is_alive() {
+                        sx += (tx * TILE as i64 + x as i64) as i128;
+                        sy += (ty * TILE as i64 + y as i64) as i128;
+                        n += 1;
+                    }
+                }
+            }
+        }
+        (n > 0).then(|| ((sx / n) as i64, (sy / n) as i64))
+    }
+}
+
+// Tile holding (x, y) and the position within it
+pub fn split(x: i64, y: i64) -> (TileKey, usize, usize) {
+    let size = TILE as i64;
+    (
+        (x.div_euclid(size), y.div_euclid(size)),
+        x.rem_euclid(size) as usize,
+        y.rem_euclid(size) as usize,
+    )
+}